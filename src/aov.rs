@@ -0,0 +1,169 @@
+use crate::color::Color;
+use crate::image::Image;
+use crate::vec3::{Point3, Vec3};
+
+// Depth written for pixels where every sample escaped to the sky
+const BACKGROUND_DEPTH: f64 = 1.0e10;
+
+/// Names of the layers returned by `Aovs::resolve`, in order.
+pub const LAYERS: [&str; 10] = [
+    "depth",
+    "normal",
+    "position",
+    "albedo",
+    "object_id",
+    "material_id",
+    "diffuse_direct",
+    "diffuse_indirect",
+    "specular_direct",
+    "specular_indirect",
+];
+
+// Indices of the denoiser's feature layers in `LAYERS`
+pub const DEPTH: usize = 0;
+pub const NORMAL: usize = 1;
+pub const ALBEDO: usize = 3;
+
+/// What one camera sample saw at its first hit. Direct light is what
+/// reaches the first hit straight from a light, an emitter or the sky;
+/// indirect light took at least one more bounce.
+#[derive(Clone, Copy, Default)]
+pub struct AovSample {
+    pub hit: bool,
+    // Distance from the ray origin to the hit
+    pub depth: f64,
+    // World-space normal on the outside of the surface
+    pub normal: Vec3,
+    pub position: Point3,
+    pub albedo: Color,
+    pub object_id: usize,
+    // Material ID from `HittableList::material_id`
+    pub material: usize,
+    pub diffuse_direct: Color,
+    pub diffuse_indirect: Color,
+    pub specular_direct: Color,
+    pub specular_indirect: Color,
+}
+
+/// Per-pixel sums of AOV samples. The IDs come from the pixel's first
+/// sample, since averaging them would be meaningless.
+#[derive(Clone, Copy, Default)]
+pub struct AovPixel {
+    samples: u64,
+    hits: u64,
+    depth: f64,
+    normal: Vec3,
+    position: Point3,
+    albedo: Color,
+    ids: Option<(usize, usize)>,
+    lobes: [Color; 4],
+}
+
+impl AovPixel {
+    pub fn push(&mut self, sample: &AovSample) {
+        if self.samples == 0 {
+            self.ids = sample.hit.then_some((sample.object_id, sample.material));
+        }
+        self.samples += 1;
+        if sample.hit {
+            self.hits += 1;
+            self.depth += sample.depth;
+            self.normal += sample.normal;
+            self.position += sample.position;
+            self.albedo += sample.albedo;
+        }
+        self.lobes[0] += sample.diffuse_direct;
+        self.lobes[1] += sample.diffuse_indirect;
+        self.lobes[2] += sample.specular_direct;
+        self.lobes[3] += sample.specular_indirect;
+    }
+
+    fn merge(&mut self, other: &AovPixel) {
+        if self.samples == 0 {
+            self.ids = other.ids;
+        }
+        self.samples += other.samples;
+        self.hits += other.hits;
+        self.depth += other.depth;
+        self.normal += other.normal;
+        self.position += other.position;
+        self.albedo += other.albedo;
+        for (lobe, other) in self.lobes.iter_mut().zip(other.lobes) {
+            *lobe += other;
+        }
+    }
+}
+
+/// Arbitrary output variables gathered alongside the beauty image. Surface
+/// layers average the samples that hit something; the light layers average
+/// over all samples with a box filter, so together with emission and the
+/// directly visible sky they add up to the box-filtered beauty image.
+#[derive(Clone)]
+pub struct Aovs {
+    width: usize,
+    height: usize,
+    pixels: Vec<AovPixel>,
+}
+
+impl Aovs {
+    pub fn new(width: usize, height: usize) -> Self {
+        Aovs {
+            width,
+            height,
+            pixels: vec![AovPixel::default(); width * height],
+        }
+    }
+
+    /// True if no samples have been gathered, e.g. after resuming a film
+    /// that was already complete.
+    pub fn is_empty(&self) -> bool {
+        self.pixels.iter().all(|p| p.samples == 0)
+    }
+
+    pub fn add(&mut self, x: usize, y: usize, pixel: &AovPixel) {
+        self.pixels[y * self.width + x].merge(pixel);
+    }
+
+    /// One image per entry of `LAYERS`. Object IDs count from 1 in the
+    /// order of the scene's top-level list, material IDs from 1 in the order
+    /// the materials were added to it; 0 is the background.
+    pub fn resolve(&self) -> Vec<Image> {
+        let mut layers = vec![Image::new(self.width, self.height); LAYERS.len()];
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let pixel = &self.pixels[y * self.width + x];
+                let mut values = [Color::default(); LAYERS.len()];
+
+                values[DEPTH] = Color::new(BACKGROUND_DEPTH, BACKGROUND_DEPTH, BACKGROUND_DEPTH);
+                if pixel.hits > 0 {
+                    let hits = pixel.hits as f64;
+                    let depth = pixel.depth / hits;
+                    values[DEPTH] = Color::new(depth, depth, depth);
+                    values[NORMAL] = pixel.normal / hits;
+                    if values[NORMAL].length_squared() > 0.0 {
+                        values[NORMAL] = values[NORMAL] / values[NORMAL].length();
+                    }
+                    values[2] = pixel.position / hits;
+                    values[ALBEDO] = pixel.albedo / hits;
+                }
+                if let Some((object, material)) = pixel.ids {
+                    let material = (material + 1) as f64;
+                    let object = (object + 1) as f64;
+                    values[4] = Color::new(object, object, object);
+                    values[5] = Color::new(material, material, material);
+                }
+                if pixel.samples > 0 {
+                    for (value, lobe) in values[6..].iter_mut().zip(pixel.lobes) {
+                        *value = lobe / pixel.samples as f64;
+                    }
+                }
+
+                for (layer, value) in layers.iter_mut().zip(values) {
+                    layer.set_pixel(x, y, value);
+                }
+            }
+        }
+        layers
+    }
+}
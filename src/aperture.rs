@@ -0,0 +1,128 @@
+use std::sync::Arc;
+
+use crate::color;
+use crate::common;
+use crate::image::Image;
+use crate::sampler;
+use crate::vec3;
+
+/// Shape of the lens opening, which out-of-focus highlights take on.
+/// Positions are relative to the aperture radius: the circle and the
+/// polygon fit in the unit disk, a mask covers the square around it.
+#[derive(Clone, Default)]
+pub enum Aperture {
+    #[default]
+    Circle,
+    // Regular polygon formed by `blades` straight blades, with a corner at
+    // `rotation` degrees
+    Polygon {
+        blades: u32,
+        rotation: f64,
+    },
+    // Grayscale image; brighter parts let more light through
+    Mask(Arc<ApertureMask>),
+}
+
+impl Aperture {
+    /// Point on the aperture, drawn in proportion to how much light passes
+    /// there.
+    pub fn sample(&self) -> (f64, f64) {
+        match self {
+            Aperture::Circle => {
+                let p = vec3::random_in_unit_disk();
+                (p.x(), p.y())
+            }
+            Aperture::Polygon { blades, rotation } => {
+                // Pick one of the triangles between the center and an edge
+                let (u1, u2) = sampler::get_2d();
+                let n = *blades as f64;
+                let k = (u1 * n).floor().min(n - 1.0);
+                let u1 = u1 * n - k;
+
+                let a0 = common::degress_to_radians(*rotation) + 2.0 * common::PI * k / n;
+                let a1 = a0 + 2.0 * common::PI / n;
+                let s = u1.sqrt();
+                let (b1, b2) = (s * (1.0 - u2), s * u2);
+                (b1 * a0.cos() + b2 * a1.cos(), b1 * a0.sin() + b2 * a1.sin())
+            }
+            Aperture::Mask(mask) => mask.sample(),
+        }
+    }
+
+    /// Radius of the circle around the shape.
+    pub fn bounding_radius(&self) -> f64 {
+        match self {
+            Aperture::Circle | Aperture::Polygon { .. } => 1.0,
+            Aperture::Mask(_) => std::f64::consts::SQRT_2,
+        }
+    }
+
+    /// Whether light passes the aperture at (x, y).
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        match self {
+            Aperture::Circle => x * x + y * y <= 1.0,
+            Aperture::Polygon { blades, rotation } => {
+                // Distance to the center along the normal of the nearest edge
+                let sector = 2.0 * common::PI / *blades as f64;
+                let angle = (y.atan2(x) - common::degress_to_radians(*rotation)).rem_euclid(sector)
+                    - sector / 2.0;
+                f64::sqrt(x * x + y * y) * angle.cos() <= f64::cos(sector / 2.0)
+            }
+            Aperture::Mask(mask) => mask.transmission(x, y) >= 0.5,
+        }
+    }
+}
+
+/// Aperture mask with a table for sampling its pixels by brightness.
+pub struct ApertureMask {
+    image: Image,
+    // Running sum of pixel luminance, row by row from the top
+    cdf: Vec<f64>,
+}
+
+impl ApertureMask {
+    pub fn new(image: Image) -> Self {
+        let mut cdf = Vec::with_capacity(image.width() * image.height());
+        let mut total = 0.0;
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                total += color::luminance(image.pixel(x, y)).max(0.0);
+                cdf.push(total);
+            }
+        }
+        ApertureMask { image, cdf }
+    }
+
+    /// True if no light passes the mask anywhere.
+    pub fn is_opaque(&self) -> bool {
+        self.cdf.last().is_none_or(|total| *total <= 0.0)
+    }
+
+    fn sample(&self) -> (f64, f64) {
+        let (u1, u2) = sampler::get_2d();
+        let total = self.cdf.last().copied().unwrap_or(0.0);
+        let target = u1 * total;
+        let i = self
+            .cdf
+            .partition_point(|c| *c <= target)
+            .min(self.cdf.len() - 1);
+
+        // Reuse where u1 fell within the pixel's share as the x offset
+        let before = if i > 0 { self.cdf[i - 1] } else { 0.0 };
+        let fx = ((target - before) / (self.cdf[i] - before)).clamp(0.0, 1.0);
+        let (width, height) = (self.image.width() as f64, self.image.height() as f64);
+        let px = (i % self.image.width()) as f64 + fx;
+        let py = (i / self.image.width()) as f64 + u2;
+        (2.0 * px / width - 1.0, 1.0 - 2.0 * py / height)
+    }
+
+    fn transmission(&self, x: f64, y: f64) -> f64 {
+        if x.abs() > 1.0 || y.abs() > 1.0 {
+            return 0.0;
+        }
+        let (width, height) = (self.image.width(), self.image.height());
+        let px = (((x + 1.0) / 2.0 * width as f64) as usize).min(width - 1);
+        let py = (((1.0 - y) / 2.0 * height as f64) as usize).min(height - 1);
+        color::luminance(self.image.pixel(px, py))
+    }
+}
@@ -1,20 +1,159 @@
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::aov::{AovPixel, AovSample};
+use crate::aperture::Aperture;
 use crate::color::{self, Color};
+use crate::colorspace::{self, Matrix3};
+use crate::common;
+use crate::film::{Film, PixelSamples, Splats};
+use crate::filter::Filter;
 use crate::hittable::{HitRecord, Hittable};
-use crate::hittable_list::HittableList;
+use crate::lens_system::LensSystem;
+use crate::material::Lobe;
+use crate::progress::{CancelToken, Progress, ProgressTracker};
+use crate::projection::Projection;
 use crate::ray::Ray;
+use crate::sampler::{self, SamplerKind};
+use crate::scene::Scene;
+use crate::stereo::{Eye, Stereo};
+use crate::tile::{self, Tile, TileOrder};
 use crate::vec3::{self, Point3, Vec3};
-use crate::{IMAGE_WIDTH, SAMPLES_PER_PIXEL, common};
 use rayon::prelude::*;
 
-const MAX_DEPTH: i32 = 50;
-const LOOK_FROM: Vec3 = Vec3::new(13.0, 2.0, 3.0);
-const LOOK_AT: Vec3 = Vec3::new(0.0, 0.0, 0.0);
 const VUP: Vec3 = Vec3::new(0.0, 1.0, 0.0);
 
+// Average number of extra samples per unconverged pixel in one adaptive pass
+const ADAPTIVE_BATCH: u64 = 8;
+// Spacing in pixels of the probe rays that look for an object to focus on
+const AUTOFOCUS_STEP: usize = 4;
+
+thread_local! {
+    // Rays traced by the current worker thread, for progress reporting
+    static RAY_COUNT: Cell<u64> = const { Cell::new(0) };
+}
+
+pub struct AdaptiveSampling {
+    // At least 2, so that every pixel has an error estimate
+    pub min_samples: u64,
+    pub max_samples: u64,
+    // Relative standard error of a pixel's luminance at which it is converged
+    pub threshold: f64,
+}
+
+/// What autofocus puts in focus.
+#[derive(Clone)]
+pub enum Focus {
+    // The point the camera looks at
+    LookAt,
+    // Whatever is seen at an image pixel, counted from the top left
+    Pixel(usize, usize),
+    // An object of the scene, by the name it was added with
+    Object(String),
+}
+
+/// Viewpoint and geometric lens settings. The defaults frame the book's
+/// final scene.
+#[derive(Clone, Copy)]
+pub struct Lens {
+    pub look_from: Point3,
+    pub look_at: Point3,
+    // Vertical field of view in degrees
+    pub vfov: f64,
+    // Distance to the plane in perfect focus
+    pub focus_dist: f64,
+    // Angle of the cone from a point in focus to the aperture, in degrees
+    pub defocus_angle: f64,
+}
+
+impl Default for Lens {
+    fn default() -> Self {
+        Lens {
+            look_from: Point3::new(13.0, 2.0, 3.0),
+            look_at: Point3::new(0.0, 0.0, 0.0),
+            vfov: 20.0,
+            focus_dist: 10.0,
+            defocus_angle: 0.6,
+        }
+    }
+}
+
+/// Camera settings as a photographer would give them. World units are taken
+/// to be meters. Scene radiance is relative: an exposure of f/16 at 1/100 s
+/// and ISO 100 ("sunny 16") leaves it unscaled.
+#[derive(Clone, Copy)]
+pub struct PhysicalCamera {
+    // Focal length in millimeters
+    pub focal_length: f64,
+    // Sensor width and height in millimeters
+    pub sensor_width: f64,
+    pub sensor_height: f64,
+    pub f_number: f64,
+    pub focus_distance: f64,
+    // Shutter time in seconds
+    pub shutter: f64,
+    pub iso: f64,
+}
+
+impl Default for PhysicalCamera {
+    fn default() -> Self {
+        // A 50mm lens on a full-frame sensor
+        PhysicalCamera {
+            focal_length: 50.0,
+            sensor_width: 36.0,
+            sensor_height: 24.0,
+            f_number: 16.0,
+            focus_distance: 10.0,
+            shutter: 0.01,
+            iso: 100.0,
+        }
+    }
+}
+
+impl PhysicalCamera {
+    /// The image is the largest crop of the sensor with the image's aspect
+    /// ratio, so its field of view follows from whichever sensor side limits
+    /// it. The aperture is the focal length divided by the f-number.
+    pub fn lens(&self, aspect_ratio: f64) -> Lens {
+        let half_height = self.film_size(aspect_ratio).1 / 2.0;
+        let vfov = 2.0 * f64::atan(half_height / self.focal_length);
+
+        let aperture_radius = self.focal_length / self.f_number / 2.0 / 1000.0;
+        let defocus_angle = 2.0 * f64::atan(aperture_radius / self.focus_distance);
+
+        Lens {
+            vfov: vfov.to_degrees(),
+            focus_dist: self.focus_distance,
+            defocus_angle: defocus_angle.to_degrees(),
+            ..Lens::default()
+        }
+    }
+
+    /// Width and height in millimeters of the part of the sensor the image
+    /// covers.
+    pub fn film_size(&self, aspect_ratio: f64) -> (f64, f64) {
+        let height = f64::min(self.sensor_height, self.sensor_width / aspect_ratio);
+        (height * aspect_ratio, height)
+    }
+
+    /// Factor on scene radiance for the shutter, aperture and ISO: twice the
+    /// light for every stop, relative to f/16, 1/100 s and ISO 100.
+    pub fn exposure(&self) -> f64 {
+        let reference = 0.01 * 100.0 / (16.0 * 16.0);
+        self.shutter * self.iso / (self.f_number * self.f_number) / reference
+    }
+}
+
 pub struct Camera {
     image_width: i32,
     image_height: i32,
     center: Point3,
+    look_at: Point3,
     pixel00_loc: Point3,
     pixel_delta_u: Vec3,
     pixel_delta_v: Vec3,
@@ -22,15 +161,45 @@ pub struct Camera {
     defocus_angle: f64,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+    focus_dist: f64,
+    // Camera frame: u to the right, v up, w opposite the view direction
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    pub projection: Projection,
+    // Shape of the thin lens aperture
+    pub aperture: Aperture,
+    // Traced lens that replaces the thin lens for perspective views
+    pub lens_system: Option<LensSystem>,
+    // Renders a pair of eye images side by side or stacked
+    pub stereo: Option<Stereo>,
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
+    // Keys the per-sample random streams; equal seeds give identical images
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    pub filter: Filter,
+    // Gather AOV layers in the films this camera creates
+    pub aovs: bool,
+    // Most bounces a path may take
+    pub max_depth: i32,
+    // Bounces after which Russian roulette may end paths; None never does
+    pub roulette_depth: Option<i32>,
+    // Largest luminance a camera sample may carry; None leaves samples alone
+    pub clamp: Option<f64>,
+    // Factor on every sample, e.g. from `PhysicalCamera::exposure`
+    pub exposure: f64,
+    // Applied to every sample, e.g. from `colorspace::white_balance`; None
+    // leaves colors alone
+    pub white_balance: Option<Matrix3>,
 }
 
 impl Camera {
-    pub fn new(ascpect_ratio: f64, image_width: i32, samples_per_pixel: i32) -> Self {
+    pub fn new(ascpect_ratio: f64, image_width: i32, samples_per_pixel: i32, lens: &Lens) -> Self {
         let image_height = (image_width as f64 / ascpect_ratio) as i32;
-        let center = LOOK_FROM.clone();
-        //let focal_length = (LOOK_FROM - LOOK_AT).length();
-        let focus_dist = 10.0;
-        let vfov = 20.0;
+        let center = lens.look_from;
+        let focus_dist = lens.focus_dist;
+        let vfov = lens.vfov;
         let theta = common::degress_to_radians(vfov);
         let h = f64::tan(theta / 2.0);
 
@@ -38,7 +207,7 @@ impl Camera {
         let viewport_width = viewport_height * (image_width as f64 / image_height as f64);
 
         // Calculate the u,v,w unit basis vectors for the camera coordinate frame.
-        let w = vec3::unit_vector(LOOK_FROM - LOOK_AT);
+        let w = vec3::unit_vector(lens.look_from - lens.look_at);
         let u = vec3::unit_vector(vec3::cross(VUP, w));
         let v = vec3::cross(w, u);
 
@@ -54,7 +223,7 @@ impl Camera {
         let viewport_upper_left = center - (focus_dist * w) - viewport_u / 2.0 - viewport_v / 2.0;
         let pixel00_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
 
-        let defocus_angle = 0.6;
+        let defocus_angle = lens.defocus_angle;
         // calculate the camera defocus disk basis vectors
         let defocus_radius = focus_dist * f64::tan(common::degress_to_radians(defocus_angle / 2.0));
         let defocus_disk_u = u * defocus_radius;
@@ -64,6 +233,7 @@ impl Camera {
             image_width,
             image_height,
             center,
+            look_at: lens.look_at,
             pixel00_loc,
             pixel_delta_u,
             pixel_delta_v,
@@ -71,39 +241,401 @@ impl Camera {
             defocus_angle,
             defocus_disk_u,
             defocus_disk_v,
+            focus_dist,
+            u,
+            v,
+            w,
+            projection: Projection::default(),
+            aperture: Aperture::default(),
+            lens_system: None,
+            stereo: None,
+            tile_size: 32,
+            tile_order: TileOrder::default(),
+            seed: 0,
+            sampler: SamplerKind::default(),
+            filter: Filter::default(),
+            aovs: false,
+            max_depth: 50,
+            roulette_depth: None,
+            clamp: None,
+            exposure: 1.0,
+            white_balance: None,
+        }
+    }
+
+    /// Focus distance that makes `focus` sharp, found with probe rays from
+    /// the center of the lens. Distances are measured along the view
+    /// direction, like the focus distance is. An object is focused at the
+    /// median distance of the probes that hit it, so that a partly hidden
+    /// object still gets a sensible result.
+    pub fn autofocus(&self, scene: &Scene, focus: &Focus) -> Result<f64, String> {
+        let (width, height) = (self.image_width as usize, self.image_height as usize);
+        match focus {
+            Focus::LookAt => Ok(vec3::dot(self.look_at - self.center, -self.w)),
+            Focus::Pixel(x, y) => {
+                if *x >= width || *y >= height {
+                    return Err(format!("pixel {x},{y} is outside the image"));
+                }
+                self.probe(scene, *x, *y)
+                    .map(|(depth, _)| depth)
+                    .ok_or_else(|| format!("there is nothing to focus on at pixel {x},{y}"))
+            }
+            Focus::Object(name) => {
+                let id = scene
+                    .world
+                    .object_id(name)
+                    .ok_or_else(|| format!("there is no object named '{name}'"))?;
+                let mut depths: Vec<f64> = (0..height)
+                    .step_by(AUTOFOCUS_STEP)
+                    .flat_map(|y| (0..width).step_by(AUTOFOCUS_STEP).map(move |x| (x, y)))
+                    .filter_map(|(x, y)| self.probe(scene, x, y))
+                    .filter(|(_, object_id)| *object_id == id)
+                    .map(|(depth, _)| depth)
+                    .collect();
+                if depths.is_empty() {
+                    return Err(format!("'{name}' is not in view"));
+                }
+                depths.sort_by(f64::total_cmp);
+                Ok(depths[depths.len() / 2])
+            }
         }
     }
 
-    pub fn render(&self, world: &HittableList) {
-        print!("P3\n{} {}\n255\n", self.image_width, self.image_height);
+    /// Distance along the view direction and object ID of the first surface
+    /// a pinhole camera sees through the center of pixel (x, y).
+    fn probe(&self, scene: &Scene, x: usize, y: usize) -> Option<(f64, usize)> {
+        let j = self.image_height - 1 - y as i32;
+        let pixel =
+            self.pixel00_loc + x as f64 * self.pixel_delta_u + j as f64 * self.pixel_delta_v;
+        let ray = Ray::new(self.center, pixel - self.center);
+        let mut rec = HitRecord::new();
+        if !scene.world.hit(&ray, 0.001, f64::INFINITY, &mut rec) {
+            return None;
+        }
+        Some((vec3::dot(rec.p - self.center, -self.w), rec.object_id))
+    }
 
-        for j in (0..self.image_height).rev() {
-            eprint!("\rScanning remaining: {j}");
+    pub fn film(&self) -> Film {
+        let (width, height) = (self.image_width as usize, self.image_height as usize);
+        let (width, height) = self
+            .stereo
+            .map_or((width, height), |stereo| stereo.film_size(width, height));
+        let film = Film::new(width, height);
+        if self.aovs { film.with_aovs() } else { film }
+    }
 
-            let pixel_colors: Vec<_> = (0..IMAGE_WIDTH)
-                .into_par_iter()
+    /// Renders the scene tile by tile in `tile_order`, reporting progress after
+    /// every tile. Cancelling stops workers from starting new tiles; tiles
+    /// that never ran have no samples and resolve to black.
+    pub fn render(
+        &self,
+        scene: &Scene,
+        progress: &(dyn Fn(&Progress) + Sync),
+        cancel: &CancelToken,
+    ) -> Film {
+        let mut film = self.film();
+        let samples = self.samples_per_pixel as u64;
+        self.render_pass(scene, &mut film, &|_, _| samples, samples, progress, cancel);
+        film
+    }
+
+    /// Adds passes of `pass_samples` samples per pixel over the whole image
+    /// until every pixel of `film` has `samples_per_pixel` samples or the
+    /// render is cancelled. The film is saved to `checkpoint` after each pass,
+    /// so a film loaded from it can be passed back in to resume.
+    pub fn render_progressive(
+        &self,
+        scene: &Scene,
+        film: &mut Film,
+        pass_samples: u64,
+        checkpoint: Option<&Path>,
+        progress: &(dyn Fn(&Progress) + Sync),
+        cancel: &CancelToken,
+    ) -> io::Result<()> {
+        let target = self.samples_per_pixel as u64;
+
+        while film.min_samples() < target && !cancel.is_cancelled() {
+            let samples = pass_samples.clamp(1, target - film.min_samples());
+            self.render_pass(scene, film, &|_, _| samples, target, progress, cancel);
+
+            if let Some(path) = checkpoint {
+                film.save(path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Keeps adding one-sample passes over the whole image until the next
+    /// pass would overrun `time_limit`, so every pixel ends up with the same
+    /// number of samples. Returns that number.
+    pub fn render_timed(
+        &self,
+        scene: &Scene,
+        film: &mut Film,
+        time_limit: Duration,
+        progress: &(dyn Fn(&Progress) + Sync),
+        cancel: &CancelToken,
+    ) -> u64 {
+        let start = Instant::now();
+        let mut slowest_pass = Duration::ZERO;
+
+        // Always take at least one pass so there is an image to return
+        while !cancel.is_cancelled()
+            && (film.min_samples() == 0 || start.elapsed() + slowest_pass <= time_limit)
+        {
+            let pass_start = Instant::now();
+            // The final count is not known up front, so the samplers run
+            // their open-ended sequences
+            self.render_pass(scene, film, &|_, _| 1, 1, progress, cancel);
+            slowest_pass = slowest_pass.max(pass_start.elapsed());
+        }
+
+        film.min_samples()
+    }
+
+    /// Spends the same total budget as `samples_per_pixel` would, but
+    /// unevenly: every pixel first takes `min_samples`, then further passes
+    /// hand out the remaining samples in proportion to each pixel's
+    /// relative error. Pixels stop once they are below `threshold` or reach
+    /// `max_samples`.
+    pub fn render_adaptive(
+        &self,
+        scene: &Scene,
+        film: &mut Film,
+        adaptive: &AdaptiveSampling,
+        progress: &(dyn Fn(&Progress) + Sync),
+        cancel: &CancelToken,
+    ) {
+        let (width, height) = (film.width(), film.height());
+        let budget = self.samples_per_pixel as u64 * (width * height) as u64;
+        let (min_samples, max_samples) = (adaptive.min_samples, adaptive.max_samples);
+        debug_assert!(2 <= min_samples && min_samples <= max_samples);
+
+        let mut allocation: Vec<u64> = (0..width * height)
+            .map(|i| min_samples.saturating_sub(film.samples(i % width, i / width)))
+            .collect();
+
+        while !cancel.is_cancelled() && allocation.iter().any(|n| *n > 0) {
+            self.render_pass(
+                scene,
+                film,
+                &|x, y| allocation[y * width + x],
+                max_samples,
+                progress,
+                cancel,
+            );
+
+            let used = film.total_samples();
+            if used >= budget {
+                break;
+            }
+
+            let errors: Vec<f64> = (0..width * height)
                 .map(|i| {
-                    let mut pixel_color = Color::new(0.0, 0.0, 0.0);
-                    for _ in 0..SAMPLES_PER_PIXEL {
-                        let ray = self.get_ray(i, j);
-                        pixel_color += Self::ray_color(&ray, MAX_DEPTH, world);
+                    let (x, y) = (i % width, i / width);
+                    let error = film.relative_error(x, y);
+                    if film.samples(x, y) >= max_samples || error <= adaptive.threshold {
+                        0.0
+                    } else {
+                        error.min(1.0e3)
                     }
-                    pixel_color
                 })
                 .collect();
+            let total_error: f64 = errors.iter().sum();
+            if total_error <= 0.0 {
+                break;
+            }
+
+            // Refine in several rounds so error estimates can catch up
+            let active = errors.iter().filter(|e| **e > 0.0).count() as u64;
+            let pass_budget = (budget - used).min(active * ADAPTIVE_BATCH) as f64;
+            for (i, error) in errors.iter().enumerate() {
+                let share = (pass_budget * error / total_error).ceil() as u64;
+                let headroom = max_samples - film.samples(i % width, i / width).min(max_samples);
+                allocation[i] = share.min(headroom);
+            }
+        }
+    }
+
+    /// Adds `samples(x, y)` samples to every pixel. `expected_samples` is
+    /// how many a pixel should hold once the render is done, which the
+    /// sampler spreads its points over.
+    fn render_pass(
+        &self,
+        scene: &Scene,
+        film: &mut Film,
+        samples: &(dyn Fn(usize, usize) -> u64 + Sync),
+        expected_samples: u64,
+        progress: &(dyn Fn(&Progress) + Sync),
+        cancel: &CancelToken,
+    ) {
+        let tiles = tile::tiles(film.width(), film.height(), self.tile_size, self.tile_order);
+        let tracker = ProgressTracker::new(tiles.len());
+        // Splats reach across tile borders. They are merged in tile order,
+        // holding back those that finish early, so that shared pixels sum
+        // them in the same order whatever the number of threads.
+        let film = Mutex::new((film, 0, BTreeMap::new()));
+
+        // Workers pull tiles from a shared counter so that they are started
+        // in the scheduled order.
+        let next_tile = AtomicUsize::new(0);
+        (0..rayon::current_num_threads())
+            .into_par_iter()
+            .for_each(|_| {
+                while !cancel.is_cancelled() {
+                    let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                    let Some(tile) = tiles.get(index) else {
+                        break;
+                    };
+
+                    // Sample indices continue from what the film already
+                    // holds, so that passes never reuse a random stream.
+                    let first_samples: Vec<u64> = {
+                        let (film, ..) = &*film.lock().unwrap();
+                        (tile.y0..tile.y1)
+                            .flat_map(|y| (tile.x0..tile.x1).map(move |x| (x, y)))
+                            .map(|(x, y)| film.samples(x, y))
+                            .collect()
+                    };
+
+                    let rays_before = RAY_COUNT.get();
+                    let (pixels, aovs, splats) =
+                        self.render_tile(tile, scene, samples, expected_samples, &first_samples);
+                    let rays = RAY_COUNT.get() - rays_before;
 
-            for pixel_color in pixel_colors {
-                color::write(pixel_color * (1.0 / self.samples_per_pixel as f64));
+                    let mut guard = film.lock().unwrap();
+                    let (film, next_splats, waiting) = &mut *guard;
+                    for (k, (pixel, aov)) in pixels.iter().zip(&aovs).enumerate() {
+                        let x = tile.x0 + k % (tile.x1 - tile.x0);
+                        let y = tile.y0 + k / (tile.x1 - tile.x0);
+                        film.add(x, y, pixel);
+                        film.add_aovs(x, y, aov);
+                    }
+                    waiting.insert(index, splats);
+                    while let Some(splats) = waiting.remove(next_splats) {
+                        film.add_splats(&splats);
+                        *next_splats += 1;
+                    }
+                    drop(guard);
+
+                    progress(&tracker.tile_done(rays));
+                }
+            });
+    }
+
+    fn render_tile(
+        &self,
+        tile: &Tile,
+        scene: &Scene,
+        samples: &(dyn Fn(usize, usize) -> u64 + Sync),
+        expected_samples: u64,
+        first_samples: &[u64],
+    ) -> (Vec<PixelSamples>, Vec<AovPixel>, Splats) {
+        let mut pixels = Vec::with_capacity((tile.x1 - tile.x0) * (tile.y1 - tile.y0));
+        let mut aovs = Vec::with_capacity(pixels.capacity());
+        let mut splats = Splats::new(tile, self.filter);
+        sampler::install(self.sampler.create(expected_samples, self.seed));
+
+        for y in tile.y0..tile.y1 {
+            for x in tile.x0..tile.x1 {
+                // Each eye of a stereo pair is an image of its own. Both
+                // eyes use the same sample streams, so their noise matches.
+                let (eye, ex, ey) = self.eye_pixel(x, y);
+                let region = self.eye_region(eye);
+                // Image rows run top to bottom, camera rows bottom to top
+                let j = self.image_height - 1 - ey as i32;
+                let pixel_index = (ey * self.image_width as usize + ex) as u64;
+                let first_sample = first_samples[pixels.len()];
+                let mut pixel = PixelSamples::default();
+                let mut aov = AovPixel::default();
+                for s in 0..samples(x, y) {
+                    common::seed_sample(self.seed, pixel_index, first_sample + s);
+                    sampler::start_sample(pixel_index, first_sample + s);
+                    let offset = Self::sample_square();
+                    // Film positions without a ray, such as the corners of
+                    // a fisheye image, are black
+                    let (sample, aov_sample) = match self.get_ray(ex as i32, j, offset, eye) {
+                        Some((ray, weight)) => self.trace_sample(&ray, weight, scene),
+                        None => Default::default(),
+                    };
+                    pixel.push(sample);
+                    aov.push(&aov_sample);
+                    // Camera offsets point up the image, film rows down it
+                    splats.add_sample(
+                        x as f64 + 0.5 + offset.x(),
+                        y as f64 + 0.5 - offset.y(),
+                        sample,
+                        &region,
+                    );
+                }
+                pixels.push(pixel);
+                aovs.push(aov);
+            }
+        }
+
+        sampler::uninstall();
+        (pixels, aovs, splats)
+    }
+
+    /// The eye that film pixel (x, y) shows and the pixel's position in
+    /// that eye's image. Without stereo there is a single view and no eye.
+    fn eye_pixel(&self, x: usize, y: usize) -> (Option<Eye>, usize, usize) {
+        let (width, height) = (self.image_width as usize, self.image_height as usize);
+        match &self.stereo {
+            Some(stereo) => {
+                let (eye, x, y) = stereo.split(x, y, width, height);
+                (Some(eye), x, y)
             }
+            None => (None, x, y),
         }
-        eprint!("\nDone\n");
     }
 
-    fn get_ray(&self, i: i32, j: i32) -> Ray {
-        // Construct a camera ray originating from the origin and directed at randomly
-        // sampled point around pixel location i, j
+    fn eye_region(&self, eye: Option<Eye>) -> Tile {
+        let (width, height) = (self.image_width as usize, self.image_height as usize);
+        match (&self.stereo, eye) {
+            (Some(stereo), Some(eye)) => stereo.region(eye, width, height),
+            _ => Tile {
+                x0: 0,
+                y0: 0,
+                x1: width,
+                y1: height,
+            },
+        }
+    }
 
-        let offset = Self::sample_square();
+    /// Camera ray through the sampled point of pixel (i, j) of the view from
+    /// `eye`, with the weight of the radiance it carries.
+    fn get_ray(&self, i: i32, j: i32, offset: Vec3, eye: Option<Eye>) -> Option<(Ray, f64)> {
+        let x = 2.0 * (i as f64 + 0.5 + offset.x()) / self.image_width as f64 - 1.0;
+        let y = 2.0 * (j as f64 + 0.5 + offset.y()) / self.image_height as f64 - 1.0;
+        let eye_offset = match (&self.stereo, eye) {
+            (Some(stereo), Some(eye)) => stereo.offset(eye),
+            _ => 0.0,
+        };
+        let (origin, direction, weight) = match (&self.lens_system, self.projection) {
+            (Some(lens), Projection::Perspective) => lens.camera_ray(x, y)?,
+            (None, Projection::Perspective) => {
+                return Some((self.thin_lens_ray(i, j, offset, eye_offset), 1.0));
+            }
+            (_, projection) => {
+                let aspect = self.image_width as f64 / self.image_height as f64;
+                let view_height = self.pixel_delta_v.length() * self.image_height as f64;
+                let (origin, direction) = projection.camera_ray(x, y, aspect, view_height)?;
+                (origin, direction, 1.0)
+            }
+        };
+        let origin = origin + eye_offset * self.projection.stereo_baseline(x);
+        let ray = Ray::new(
+            self.center + origin.x() * self.u + origin.y() * self.v + origin.z() * self.w,
+            direction.x() * self.u + direction.y() * self.v + direction.z() * self.w,
+        );
+        Some((ray, weight))
+    }
+
+    fn thin_lens_ray(&self, i: i32, j: i32, offset: Vec3, eye_offset: f64) -> Ray {
+        // Construct a camera ray originating from the origin and directed at the
+        // sampled point offset from pixel location i, j
         let pixel_sample = self.pixel00_loc
             + ((i as f64 + offset.x()) * self.pixel_delta_u)
             + ((j as f64 + offset.y()) * self.pixel_delta_v);
@@ -112,53 +644,534 @@ impl Camera {
         } else {
             self.defocus_disk_sample()
         };
+
+        // A stereo eye moves sideways, and its image moves with it except at
+        // the convergence distance, where both eyes see the same point
+        let convergence = self
+            .stereo
+            .and_then(|stereo| stereo.convergence)
+            .unwrap_or(self.focus_dist);
+        let eye = eye_offset * self.u;
+        let pixel_sample = pixel_sample + (1.0 - self.focus_dist / convergence) * eye;
+        let ray_origin = ray_origin + eye;
         let ray_direction = pixel_sample - ray_origin;
         Ray::new(ray_origin, ray_direction)
     }
 
     fn sample_square() -> Vec3 {
-        // Returns a vector to a random point in the [-.5, -.5] - [+.5, +.5] unit square
-        Vec3::new(
-            common::random_double() - 0.5,
-            common::random_double() - 0.5,
-            0.0,
-        )
+        // Returns a vector to a sampled point in the [-.5, -.5] - [+.5, +.5] unit square
+        let (u1, u2) = sampler::get_2d();
+        Vec3::new(u1 - 0.5, u2 - 0.5, 0.0)
     }
 
     fn defocus_disk_sample(&self) -> Point3 {
         // return a random point in the camera defocus disk
-        let p = vec3::random_in_unit_disk();
-        self.center + (p.x() * self.defocus_disk_u) + (p.y() * self.defocus_disk_v)
+        let (x, y) = self.aperture.sample();
+        self.center + (x * self.defocus_disk_u) + (y * self.defocus_disk_v)
     }
 
-    fn ray_color(r: &Ray, depth: i32, world: &dyn Hittable) -> Color {
+    /// The exposed radiance of a camera sample, scaled by the camera ray's
+    /// `weight`, and the AOVs of its first hit. With `clamp` set, samples
+    /// brighter than the limit are scaled down to it. That removes fireflies
+    /// at the cost of bias: the image converges to something darker than
+    /// the true result.
+    fn trace_sample(&self, r: &Ray, weight: f64, scene: &Scene) -> (Color, AovSample) {
+        let (mut color, mut aov) = self.trace_path(r, scene);
+        if let Some(m) = &self.white_balance {
+            color = colorspace::transform(m, color);
+            for lobe in [
+                &mut aov.diffuse_direct,
+                &mut aov.diffuse_indirect,
+                &mut aov.specular_direct,
+                &mut aov.specular_indirect,
+            ] {
+                *lobe = colorspace::transform(m, *lobe);
+            }
+        }
+
+        let mut scale = self.exposure * weight;
+        if let Some(max) = self.clamp {
+            let luminance = scale * color::luminance(color);
+            if luminance > max {
+                scale *= max / luminance;
+            }
+        }
+        aov.diffuse_direct *= scale;
+        aov.diffuse_indirect *= scale;
+        aov.specular_direct *= scale;
+        aov.specular_indirect *= scale;
+        (scale * color, aov)
+    }
+
+    /// Radiance along a camera ray, together with the AOVs of its first hit.
+    /// This is `ray_color` with the first bounce unrolled, so that the light
+    /// arriving there can be split up by lobe.
+    fn trace_path(&self, r: &Ray, scene: &Scene) -> (Color, AovSample) {
+        let mut aov = AovSample::default();
+        RAY_COUNT.set(RAY_COUNT.get() + 1);
+        let mut rec = HitRecord::new();
+        if !scene.world.hit(r, 0.001, f64::INFINITY, &mut rec) {
+            return (Self::sky(r), aov);
+        }
+
+        let mat = rec.mat.as_ref().unwrap();
+        aov.hit = true;
+        aov.depth = rec.t * r.direction().length();
+        aov.normal = rec.outwards_normal();
+        aov.position = rec.p;
+        aov.albedo = mat.albedo(&rec);
+        aov.object_id = rec.object_id;
+        aov.material = scene.world.material_id(mat).unwrap_or_default();
+
+        let color_from_emission = mat.emitted(rec.u, rec.v, &rec.p);
+        // Delta lights are only reached through `eval`, which is diffuse
+        let color_from_lights = Self::direct_lighting(r, &rec, scene);
+        aov.diffuse_direct = color_from_lights;
+
+        let mut scattered = Ray::default();
+        let mut attenuation = Color::default();
+        let mut color = color_from_emission + color_from_lights;
+        if let Some(lobe) = mat.scatter(r, &rec, &mut attenuation, &mut scattered)
+            && let Some(weight) = self.roulette(self.max_depth, attenuation)
+        {
+            let attenuation = weight * attenuation;
+            let (emitted, reflected) =
+                self.radiance(&scattered, self.max_depth - 1, attenuation, scene);
+            let (direct, indirect) = match lobe {
+                Lobe::Diffuse => (&mut aov.diffuse_direct, &mut aov.diffuse_indirect),
+                Lobe::Specular => (&mut aov.specular_direct, &mut aov.specular_indirect),
+            };
+            *direct += attenuation * emitted;
+            *indirect += attenuation * reflected;
+            color += attenuation * (emitted + reflected);
+        }
+        (color, aov)
+    }
+
+    fn ray_color(&self, r: &Ray, depth: i32, throughput: Color, scene: &Scene) -> Color {
+        let (emitted, reflected) = self.radiance(r, depth, throughput, scene);
+        emitted + reflected
+    }
+
+    /// Radiance along `r`, split into what the first thing it meets emits
+    /// (the sky included) and what that surface reflects. `throughput` is
+    /// the weight the path carries up to here, for Russian roulette.
+    fn radiance(&self, r: &Ray, depth: i32, throughput: Color, scene: &Scene) -> (Color, Color) {
         if depth <= 0 {
-            return Color::new(0.0, 0.0, 0.0);
+            return (Color::default(), Color::default());
         }
 
+        RAY_COUNT.set(RAY_COUNT.get() + 1);
         let mut rec = HitRecord::new();
-        if world.hit(r, 0.001, std::f64::INFINITY, &mut rec) {
+        if scene.world.hit(r, 0.001, f64::INFINITY, &mut rec) {
+            let mat = rec.mat.as_ref().unwrap();
+            let color_from_emission = mat.emitted(rec.u, rec.v, &rec.p);
+            let color_from_lights = Self::direct_lighting(r, &rec, scene);
+
             let mut scattered = Ray::default();
             let mut attenuation = Color::default();
 
-            if rec
-                .mat
-                .as_ref()
-                .unwrap()
+            if mat
                 .scatter(r, &rec, &mut attenuation, &mut scattered)
+                .is_some()
+                && let Some(weight) = self.roulette(depth, throughput * attenuation)
             {
-                return attenuation * Self::ray_color(&scattered, depth - 1, world);
+                let attenuation = weight * attenuation;
+                let incoming =
+                    self.ray_color(&scattered, depth - 1, throughput * attenuation, scene);
+                return (
+                    color_from_emission,
+                    color_from_lights + attenuation * incoming,
+                );
             }
-            return Color::new(0.0, 0.0, 0.0);
+            return (color_from_emission, color_from_lights);
+        }
 
-            //let direction = random_on_hemisphere(&rec.normal);
-            /* let direction = rec.normal + vec3::random_unit_vector();
-            return 0.5 * Self::ray_color(&Ray::new(rec.p, direction), depth - 1, world); */
-            //return 0.5 * (rec.normal + Color::new(1.0, 1.0, 1.0));
+        (Self::sky(r), Color::default())
+    }
+
+    /// Russian roulette for a path that has just scattered at remaining
+    /// depth `depth`. Once it is past `roulette_depth` bounces, the path
+    /// survives with a probability that follows its throughput, and
+    /// survivors are weighted up by the returned factor. This ends dim paths
+    /// early without bias. Returns None when the path is terminated.
+    fn roulette(&self, depth: i32, throughput: Color) -> Option<f64> {
+        let Some(start) = self.roulette_depth else {
+            return Some(1.0);
+        };
+        let bounces = self.max_depth - depth + 1;
+        if bounces < start {
+            return Some(1.0);
         }
 
+        // Capped below one so that even paths through clear glass end
+        let survival = throughput
+            .x()
+            .max(throughput.y())
+            .max(throughput.z())
+            .min(0.95);
+        (sampler::get_1d() < survival).then(|| 1.0 / survival)
+    }
+
+    fn sky(r: &Ray) -> Color {
         let unit_direction = vec3::unit_vector(r.direction());
         let t = 0.5 * (unit_direction.y() + 1.0);
-        (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0)
+        colorspace::input((1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0))
+    }
+
+    fn direct_lighting(r: &Ray, rec: &HitRecord, scene: &Scene) -> Color {
+        // Next-event estimation towards every delta light. Scattered rays can
+        // never hit these lights, so their contribution is not counted twice.
+        let mat = rec.mat.as_ref().unwrap();
+        let mut color = Color::default();
+
+        for light in &scene.lights {
+            let Some(sample) = light.sample_li(&rec.p) else {
+                continue;
+            };
+
+            let f = mat.eval(r, rec, sample.wi);
+            if f.near_zero() {
+                continue;
+            }
+
+            RAY_COUNT.set(RAY_COUNT.get() + 1);
+            let shadow_ray = Ray::new(rec.p, sample.wi);
+            let mut shadow_rec = HitRecord::new();
+            if scene.world.hit(
+                &shadow_ray,
+                0.001,
+                sample.distance * (1.0 - 1.0e-6),
+                &mut shadow_rec,
+            ) {
+                continue;
+            }
+
+            color += f * sample.radiance;
+        }
+
+        color
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::{env, fs};
+
+    use super::*;
+    use crate::aov::LAYERS;
+    use crate::filter::FilterKind;
+    use crate::hittable_list::HittableList;
+    use crate::light::PointLight;
+    use crate::material::{DiffuseLight, Lambertian};
+    use crate::quad::Quad;
+    use crate::sphere::Sphere;
+
+    /// Distance from the default viewpoint to the point it looks at.
+    fn look_at_distance() -> f64 {
+        Point3::new(13.0, 2.0, 3.0).length()
+    }
+
+    /// A camera at the default viewpoint, looking at a matte sphere of
+    /// radius 1 around the look-at point, narrowly enough that it fills the
+    /// view. The ground lies far out of view.
+    fn sphere_in_view() -> (Camera, Scene) {
+        let lens = Lens {
+            vfov: 1.0,
+            ..Lens::default()
+        };
+        let camera = Camera::new(1.0, 32, 1, &lens);
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut world = HittableList::new();
+        world.add_named(
+            "matte",
+            Box::new(Sphere::new(Point3::default(), 1.0, material.clone())),
+        );
+        world.add_named(
+            "ground",
+            Box::new(Sphere::new(Point3::new(0.0, -1000.0, 0.0), 990.0, material)),
+        );
+        (camera, Scene::new(world))
+    }
+
+    #[test]
+    fn focuses_on_the_look_at_point() {
+        let camera = Camera::new(1.5, 60, 1, &Lens::default());
+        let distance = camera.autofocus(&Scene::default(), &Focus::LookAt).unwrap();
+        assert!((distance - look_at_distance()).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn focuses_on_the_front_of_an_object() {
+        let (camera, scene) = sphere_in_view();
+        let object = |name: &str| camera.autofocus(&scene, &Focus::Object(name.to_string()));
+        let front = look_at_distance() - 1.0;
+        // The sphere curves back by less than 0.01 within the view
+        assert!((object("matte").unwrap() - front).abs() < 0.01);
+        let center = camera.autofocus(&scene, &Focus::Pixel(16, 16)).unwrap();
+        assert!((center - front).abs() < 1.0e-3);
+        assert!(object("ground").is_err());
+        assert!(object("glass").is_err());
+    }
+
+    #[test]
+    fn rejects_pixels_outside_the_image() {
+        let (camera, scene) = sphere_in_view();
+        assert!(camera.autofocus(&scene, &Focus::Pixel(31, 31)).is_ok());
+        assert!(camera.autofocus(&scene, &Focus::Pixel(32, 0)).is_err());
+        assert!(camera.autofocus(&scene, &Focus::Pixel(0, 32)).is_err());
+    }
+
+    #[test]
+    fn stereo_center_rays_cross_at_the_convergence_distance() {
+        let lens = Lens {
+            defocus_angle: 0.0,
+            ..Lens::default()
+        };
+        let mut camera = Camera::new(1.0, 101, 1, &lens);
+        camera.stereo = Some(Stereo {
+            convergence: Some(4.0),
+            ..Stereo::default()
+        });
+        let stereo = camera.stereo.unwrap();
+        for eye in [Eye::Left, Eye::Right] {
+            let ray = camera.thin_lens_ray(50, 50, Vec3::default(), stereo.offset(eye));
+            // The point of the ray 4 units along the view direction lies on
+            // the camera's axis
+            let t = (4.0 - vec3::dot(ray.origin() - camera.center, -camera.w))
+                / vec3::dot(ray.direction(), -camera.w);
+            let p = ray.at(t) - camera.center;
+            assert!(vec3::dot(p, camera.u).abs() < 1.0e-9);
+            assert!(vec3::dot(p, camera.v).abs() < 1.0e-9);
+        }
+    }
+
+    /// With a single bounce, a white diffuse plane under a point light shows
+    /// only next-event estimation: the irradiance I cos(theta) / r^2 over pi.
+    #[test]
+    fn point_light_follows_the_inverse_square_law() {
+        let white = Arc::new(Lambertian::new(Color::new(1.0, 1.0, 1.0)));
+        let mut world = HittableList::new();
+        world.add(Box::new(Quad::new(
+            Point3::new(-50.0, 0.0, -50.0),
+            Vec3::new(100.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 100.0),
+            white,
+        )));
+        let mut scene = Scene::new(world);
+        let light = Point3::new(0.5, 2.0, -0.5);
+        let intensity = 4.0;
+        scene.add_light(Arc::new(PointLight::new(
+            light,
+            Color::new(intensity, intensity, intensity),
+        )));
+
+        let lens = Lens {
+            look_from: Point3::new(0.0, 3.0, 3.0),
+            look_at: Point3::new(0.0, 0.0, 0.0),
+            vfov: 60.0,
+            focus_dist: 1.0,
+            defocus_angle: 0.0,
+        };
+        let mut camera = Camera::new(1.0, 16, 1, &lens);
+        camera.max_depth = 1;
+        camera.aovs = true;
+        let film = camera.render(&scene, &|_| {}, &CancelToken::new());
+        let image = film.resolve();
+        let position = LAYERS.iter().position(|name| *name == "position").unwrap();
+        let positions = &film.aovs().unwrap().resolve()[position];
+
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                let p = positions.pixel(x, y);
+                let to_light = light - Point3::new(p.x(), p.y(), p.z());
+                let r = to_light.length();
+                let cos_theta = to_light.y() / r;
+                let expected = intensity * cos_theta / (r * r) / common::PI;
+                let c = image.pixel(x, y);
+                for value in [c.x(), c.y(), c.z()] {
+                    assert!((value - expected).abs() < 1.0e-9 * expected.max(1.0));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn resumed_render_matches_an_uninterrupted_one() {
+        let (mut camera, scene) = sphere_in_view();
+        camera.samples_per_pixel = 8;
+        // Its strata depend on the expected count, which must not change
+        // between the passes
+        camera.sampler = SamplerKind::Stratified;
+        let cancel = CancelToken::new();
+
+        let uninterrupted = camera.render(&scene, &|_| {}, &cancel);
+
+        // Stop after the first pass and continue from its checkpoint
+        let path = env::temp_dir().join(format!("resume-{}.ckpt", std::process::id()));
+        let mut interrupted = camera.film();
+        camera.render_pass(&scene, &mut interrupted, &|_, _| 4, 8, &|_| {}, &cancel);
+        interrupted.save(&path).unwrap();
+        let mut resumed = Film::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        camera
+            .render_progressive(&scene, &mut resumed, 4, None, &|_| {}, &cancel)
+            .unwrap();
+
+        // The passes sum their samples in a different order, so allow for
+        // rounding; a reused random stream would be far off
+        assert_eq!(resumed.min_samples(), 8);
+        let (a, b) = (resumed.resolve(), uninterrupted.resolve());
+        for y in 0..a.height() {
+            for x in 0..a.width() {
+                let d = a.pixel(x, y) - b.pixel(x, y);
+                assert!(d.length() < 1.0e-12, "pixel ({x}, {y}) differs");
+            }
+        }
+    }
+
+    #[test]
+    fn adaptive_sampling_stops_early_on_flat_pixels() {
+        // A matte wall fills the left half of the view, the sky the right
+        let matte = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut world = HittableList::new();
+        world.add(Box::new(Quad::new(
+            Point3::new(-10.0, -10.0, -1.0),
+            Vec3::new(10.0, 0.0, 0.0),
+            Vec3::new(0.0, 20.0, 0.0),
+            matte,
+        )));
+        let scene = Scene::new(world);
+        let lens = Lens {
+            look_from: Point3::new(0.0, 0.0, 0.0),
+            look_at: Point3::new(0.0, 0.0, -1.0),
+            vfov: 60.0,
+            focus_dist: 1.0,
+            defocus_angle: 0.0,
+        };
+        let camera = Camera::new(1.0, 16, 16, &lens);
+        let adaptive = AdaptiveSampling {
+            min_samples: 4,
+            max_samples: 64,
+            threshold: 0.01,
+        };
+        let mut film = camera.film();
+        camera.render_adaptive(&scene, &mut film, &adaptive, &|_| {}, &CancelToken::new());
+
+        let mut wall_samples = 0;
+        for y in 0..16 {
+            for x in 0..16 {
+                let samples = film.samples(x, y);
+                if x < 8 {
+                    wall_samples += samples;
+                } else {
+                    assert_eq!(samples, adaptive.min_samples, "sky pixel ({x}, {y})");
+                }
+            }
+        }
+        // The samples saved on the sky go to the wall
+        assert!(wall_samples > 128 * 4 * adaptive.min_samples);
+    }
+
+    #[test]
+    fn thread_count_does_not_change_the_image() {
+        let (mut camera, scene) = sphere_in_view();
+        camera.samples_per_pixel = 4;
+        camera.tile_size = 4;
+        // A wide filter splats across tile borders, where tiles meet in
+        // whatever order the threads finish them
+        camera.filter = Filter::new(FilterKind::Tent, 2.0);
+        let render = |threads| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            pool.install(|| camera.render(&scene, &|_| {}, &CancelToken::new()))
+                .resolve()
+        };
+
+        let bits = |c: Color| [c.x().to_bits(), c.y().to_bits(), c.z().to_bits()];
+        let (serial, parallel) = (render(1), render(8));
+        for y in 0..serial.height() {
+            for x in 0..serial.width() {
+                assert_eq!(bits(serial.pixel(x, y)), bits(parallel.pixel(x, y)));
+            }
+        }
+    }
+
+    #[test]
+    fn material_ids_do_not_depend_on_the_view() {
+        let red = Arc::new(Lambertian::new(Color::new(0.8, 0.1, 0.1)));
+        let blue = Arc::new(Lambertian::new(Color::new(0.1, 0.1, 0.8)));
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(Point3::new(-1.0, 0.0, 0.0), 0.8, red)));
+        world.add(Box::new(Sphere::new(Point3::new(1.0, 0.0, 0.0), 0.8, blue)));
+        let scene = Scene::new(world);
+        let material = LAYERS
+            .iter()
+            .position(|name| *name == "material_id")
+            .unwrap();
+
+        // Seen from the front the red sphere is on the left, from behind on
+        // the right. Either way it keeps the first ID.
+        for (z, red_x) in [(5.0, 11), (-5.0, 20)] {
+            let lens = Lens {
+                look_from: Point3::new(0.0, 0.0, z),
+                look_at: Point3::new(0.0, 0.0, 0.0),
+                vfov: 40.0,
+                focus_dist: 1.0,
+                defocus_angle: 0.0,
+            };
+            let mut camera = Camera::new(2.0, 32, 1, &lens);
+            camera.aovs = true;
+            let film = camera.render(&scene, &|_| {}, &CancelToken::new());
+            let ids = &film.aovs().unwrap().resolve()[material];
+            assert_eq!(ids.pixel(red_x, 8).x(), 1.0);
+            assert_eq!(ids.pixel(31 - red_x, 8).x(), 2.0);
+        }
+    }
+
+    #[test]
+    fn clamp_scales_bright_samples_down_to_the_limit() {
+        let light = Arc::new(DiffuseLight::new(Color::new(40.0, 20.0, 10.0)));
+        let mut world = HittableList::new();
+        world.add(Box::new(Sphere::new(
+            Point3::new(0.0, 0.0, -5.0),
+            1.0,
+            light,
+        )));
+        let scene = Scene::new(world);
+        let mut camera = Camera::new(1.0, 8, 1, &Lens::default());
+        camera.clamp = Some(2.0);
+
+        let r = Ray::new(Point3::default(), Vec3::new(0.0, 0.0, -1.0));
+        let (unclamped, _) = camera.trace_path(&r, &scene);
+        let (clamped, _) = camera.trace_sample(&r, 1.0, &scene);
+        assert!((color::luminance(clamped) - 2.0).abs() < 1.0e-12);
+        // Only the brightness changes, not the hue
+        let scale = clamped.x() / unclamped.x();
+        assert!(scale < 1.0);
+        for (c, u) in [(clamped.y(), unclamped.y()), (clamped.z(), unclamped.z())] {
+            assert!((c - scale * u).abs() < 1.0e-12);
+        }
+    }
+
+    #[test]
+    fn clamp_leaves_dim_samples_alone() {
+        let mut camera = Camera::new(1.0, 8, 1, &Lens::default());
+        camera.clamp = Some(2.0);
+        // The sky's luminance is at most 1
+        let r = Ray::new(Point3::default(), Vec3::new(0.0, 1.0, 0.0));
+        let (clamped, _) = camera.trace_sample(&r, 1.0, &Scene::default());
+        camera.clamp = None;
+        let (unclamped, _) = camera.trace_sample(&r, 1.0, &Scene::default());
+        for (c, u) in [
+            (clamped.x(), unclamped.x()),
+            (clamped.y(), unclamped.y()),
+            (clamped.z(), unclamped.z()),
+        ] {
+            assert_eq!(c, u);
+        }
     }
 }
@@ -1,22 +1,66 @@
+use std::io::{self, Write};
+
+use crate::colorspace;
 use crate::common;
+use crate::display::DisplayTransform;
+use crate::image::Image;
 use crate::vec3::Vec3;
 
 pub type Color = Vec3;
 
-pub fn write(pixel_color: Color) {
-    let [r, g, b] = [pixel_color.x(), pixel_color.y(), pixel_color.z()].map(linear_to_gamma);
+/// Relative luminance of a linear color in the working space.
+pub fn luminance(c: Color) -> f64 {
+    colorspace::working().luminance(c)
+}
+
+/// False color for `t` in [0, 1], running from blue through green to red.
+pub fn heat(t: f64) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    if t < 0.5 {
+        Color::new(0.0, 2.0 * t, 1.0 - 2.0 * t)
+    } else {
+        Color::new(2.0 * t - 1.0, 2.0 - 2.0 * t, 0.0)
+    }
+}
 
-    let ri = (256.0 * common::clamp(r, 0.0, 0.999)) as i32;
-    let gi = (256.0 * common::clamp(g, 0.0, 0.999)) as i32;
-    let bi = (256.0 * common::clamp(b, 0.0, 0.999)) as i32;
+/// Writes the image as an ASCII PPM, passing every pixel through `display`.
+pub fn write_image(
+    out: &mut impl Write,
+    image: &Image,
+    display: &DisplayTransform,
+) -> io::Result<()> {
+    write!(out, "P3\n{} {}\n255\n", image.width(), image.height())?;
 
-    println!("{ri} {gi} {bi}");
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            write(out, display.apply(image.pixel(x, y)))?;
+        }
+    }
+    Ok(())
 }
 
-fn linear_to_gamma(linear_component: f64) -> f64 {
-    if linear_component > 0.0 {
-        return f64::sqrt(linear_component);
+/// Writes the image as a little-endian PFM, keeping linear floating point
+/// values for layers that are not meant to be viewed directly.
+pub fn write_pfm(out: &mut impl Write, image: &Image) -> io::Result<()> {
+    write!(out, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+
+    // PFM stores the bottom row first
+    for y in (0..image.height()).rev() {
+        for x in 0..image.width() {
+            let c = image.pixel(x, y);
+            for v in [c.x(), c.y(), c.z()] {
+                out.write_all(&(v as f32).to_le_bytes())?;
+            }
+        }
     }
+    Ok(())
+}
+
+/// Writes one display-encoded pixel, quantized to 8 bits.
+pub fn write(out: &mut impl Write, pixel_color: Color) -> io::Result<()> {
+    let ri = (256.0 * common::clamp(pixel_color.x(), 0.0, 0.999)) as i32;
+    let gi = (256.0 * common::clamp(pixel_color.y(), 0.0, 0.999)) as i32;
+    let bi = (256.0 * common::clamp(pixel_color.z(), 0.0, 0.999)) as i32;
 
-    0.0
+    writeln!(out, "{ri} {gi} {bi}")
 }
@@ -0,0 +1,211 @@
+use std::sync::OnceLock;
+
+use crate::color::Color;
+
+pub type Matrix3 = [[f64; 3]; 3];
+
+/// RGB space the renderer computes in. Colors written in the scene and in
+/// texture files are linear Rec.709 and are converted on the way in; the
+/// rendered image is converted back to Rec.709 for display.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorSpace {
+    // Linear sRGB primaries
+    #[default]
+    Rec709,
+    // ACES AP1 primaries, adapted from the ACES white point to D65
+    AcesCg,
+    Rec2020,
+}
+
+static WORKING: OnceLock<ColorSpace> = OnceLock::new();
+
+/// Makes `space` the working space. Call it once, before building the scene
+/// or working with any color: the first use of the working space fixes it,
+/// and changing it afterwards would leave earlier colors in the old one.
+pub fn set_working(space: ColorSpace) {
+    let fixed = *WORKING.get_or_init(|| space);
+    assert!(
+        fixed == space,
+        "the working space was changed after its first use"
+    );
+}
+
+pub fn working() -> ColorSpace {
+    *WORKING.get_or_init(ColorSpace::default)
+}
+
+/// Converts a color given in linear Rec.709 into the working space.
+pub fn input(c: Color) -> Color {
+    static MATRIX: OnceLock<Option<Matrix3>> = OnceLock::new();
+    let m = MATRIX.get_or_init(|| ColorSpace::Rec709.conversion(working()));
+    m.as_ref().map_or(c, |m| transform(m, c))
+}
+
+impl ColorSpace {
+    /// RGB to CIE XYZ, all with a D65 white.
+    pub fn rgb_to_xyz(self) -> Matrix3 {
+        match self {
+            // IEC 61966-2-1, whose middle row gives the usual luma weights
+            ColorSpace::Rec709 => [
+                [0.4124, 0.3576, 0.1805],
+                [0.2126, 0.7152, 0.0722],
+                [0.0193, 0.1192, 0.9505],
+            ],
+            ColorSpace::AcesCg => [
+                [0.6522375, 0.1282361, 0.1699822],
+                [0.2676722, 0.6743400, 0.0579878],
+                [-0.0053818, 0.0013691, 1.0930705],
+            ],
+            ColorSpace::Rec2020 => [
+                [0.6369580, 0.1446169, 0.1688810],
+                [0.2627002, 0.6779981, 0.0593017],
+                [0.0000000, 0.0280727, 1.0609851],
+            ],
+        }
+    }
+
+    pub fn xyz_to_rgb(self) -> Matrix3 {
+        inverse(&self.rgb_to_xyz())
+    }
+
+    /// Relative luminance of `c`, a color in this space.
+    pub fn luminance(self, c: Color) -> f64 {
+        let [r, g, b] = self.rgb_to_xyz()[1];
+        r * c.x() + g * c.y() + b * c.z()
+    }
+
+    /// Matrix converting from this space to `to`; None if they are the same.
+    pub fn conversion(self, to: ColorSpace) -> Option<Matrix3> {
+        (self != to).then(|| multiply(&to.xyz_to_rgb(), &self.rgb_to_xyz()))
+    }
+
+    /// `c` converted from this space to `to`.
+    pub fn convert(self, c: Color, to: ColorSpace) -> Color {
+        self.conversion(to).map_or(c, |m| transform(&m, c))
+    }
+}
+
+/// Matrix that white balances colors in `space` for light of the given color
+/// temperature, so that such light comes out neutral. 6500K leaves colors
+/// nearly untouched; lower temperatures turn the image bluer.
+pub fn white_balance(kelvin: f64, space: ColorSpace) -> Matrix3 {
+    let adapt = chromatic_adaptation(white_point(kelvin), (0.3127, 0.3290));
+    multiply(&space.xyz_to_rgb(), &multiply(&adapt, &space.rgb_to_xyz()))
+}
+
+/// Chromaticity of light of the given color temperature: the Planckian locus
+/// for warm light, the CIE daylight locus from 4000K up. Both fits are valid
+/// between 1667K and 25000K.
+fn white_point(kelvin: f64) -> (f64, f64) {
+    let t = kelvin.clamp(1667.0, 25000.0);
+    let (t2, t3) = (t * t, t * t * t);
+    if t < 4000.0 {
+        // Kim et al., "Design of advanced color temperature control system
+        // for HDTV applications"
+        let x = -0.2661239e9 / t3 - 0.2343589e6 / t2 + 0.8776956e3 / t + 0.179910;
+        let y = if t < 2222.0 {
+            -1.1063814 * x * x * x - 1.34811020 * x * x + 2.18555832 * x - 0.20219683
+        } else {
+            -0.9549476 * x * x * x - 1.37418593 * x * x + 2.09137015 * x - 0.16748867
+        };
+        (x, y)
+    } else {
+        let x = if t <= 7000.0 {
+            -4.6070e9 / t3 + 2.9678e6 / t2 + 0.09911e3 / t + 0.244063
+        } else {
+            -2.0064e9 / t3 + 1.9018e6 / t2 + 0.24748e3 / t + 0.237040
+        };
+        (x, -3.0 * x * x + 2.87 * x - 0.275)
+    }
+}
+
+/// Bradford transform in XYZ that maps white `from` to white `to`.
+fn chromatic_adaptation(from: (f64, f64), to: (f64, f64)) -> Matrix3 {
+    const BRADFORD: Matrix3 = [
+        [0.8951, 0.2664, -0.1614],
+        [-0.7502, 1.7135, 0.0367],
+        [0.0389, -0.0685, 1.0296],
+    ];
+    let xyz = |(x, y): (f64, f64)| Color::new(x / y, 1.0, (1.0 - x - y) / y);
+    let source = transform(&BRADFORD, xyz(from));
+    let target = transform(&BRADFORD, xyz(to));
+    let scale = [
+        [target.x() / source.x(), 0.0, 0.0],
+        [0.0, target.y() / source.y(), 0.0],
+        [0.0, 0.0, target.z() / source.z()],
+    ];
+    multiply(&inverse(&BRADFORD), &multiply(&scale, &BRADFORD))
+}
+
+pub fn transform(m: &Matrix3, c: Color) -> Color {
+    let [x, y, z] = m.map(|row| row[0] * c.x() + row[1] * c.y() + row[2] * c.z());
+    Color::new(x, y, z)
+}
+
+fn multiply(a: &Matrix3, b: &Matrix3) -> Matrix3 {
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+fn inverse(m: &Matrix3) -> Matrix3 {
+    // Transposed cofactors over the determinant
+    let cofactor = |i: usize, j: usize| {
+        let (r0, r1) = ((i + 1) % 3, (i + 2) % 3);
+        let (c0, c1) = ((j + 1) % 3, (j + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let det = (0..3).map(|j| m[0][j] * cofactor(0, j)).sum::<f64>();
+    let mut inv = [[0.0; 3]; 3];
+    for (i, row) in inv.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = cofactor(j, i) / det;
+        }
+    }
+    inv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Color, b: Color, tolerance: f64) {
+        assert!((a - b).length() < tolerance, "{a} != {b}");
+    }
+
+    #[test]
+    fn conversions_keep_white_and_round_trip() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let orange = Color::new(0.8, 0.4, 0.1);
+        for space in [ColorSpace::AcesCg, ColorSpace::Rec2020] {
+            assert_close(ColorSpace::Rec709.convert(white, space), white, 1.0e-3);
+            let there = ColorSpace::Rec709.convert(orange, space);
+            assert_close(space.convert(there, ColorSpace::Rec709), orange, 1.0e-12);
+            assert!((space.luminance(there) - ColorSpace::Rec709.luminance(orange)).abs() < 1.0e-3);
+        }
+    }
+
+    #[test]
+    fn white_balance_neutralizes_its_illuminant() {
+        let daylight = white_balance(6504.0, ColorSpace::Rec709);
+        assert_close(
+            transform(&daylight, Color::new(0.5, 0.2, 0.7)),
+            Color::new(0.5, 0.2, 0.7),
+            1.0e-3,
+        );
+
+        // Light of the balanced temperature, seen under D65, comes out gray
+        let (x, y) = white_point(3200.0);
+        let tungsten = transform(
+            &ColorSpace::Rec709.xyz_to_rgb(),
+            Color::new(x / y, 1.0, (1.0 - x - y) / y),
+        );
+        assert!(tungsten.x() > tungsten.z());
+        let balanced = transform(&white_balance(3200.0, ColorSpace::Rec709), tungsten);
+        assert_close(balanced, Color::new(1.0, 1.0, 1.0), 1.0e-3);
+    }
+}
@@ -1,13 +1,73 @@
-use rand::Rng;
+use std::cell::RefCell;
 
 pub use std::f64::consts::PI;
 pub fn degress_to_radians(degrees: f64) -> f64 {
     degrees * PI / 180.0
 }
 
+/// PCG-XSH-RR 32-bit generator (O'Neill 2014).
+#[derive(Clone)]
+pub struct Pcg32 {
+    state: u64,
+    inc: u64,
+}
+
+impl Pcg32 {
+    const MULTIPLIER: u64 = 6364136223846793005;
+
+    pub fn new(initstate: u64, initseq: u64) -> Self {
+        let mut rng = Pcg32 {
+            state: 0,
+            inc: (initseq << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(initstate);
+        rng.next_u32();
+        rng
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(Self::MULTIPLIER).wrapping_add(self.inc);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    pub fn next_f64(&mut self) -> f64 {
+        // 53 random bits in [0.0, 1.0)
+        let bits = ((self.next_u32() as u64) << 21) ^ ((self.next_u32() as u64) >> 11);
+        bits as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+}
+
+thread_local! {
+    static RNG: RefCell<Pcg32> = RefCell::new(Pcg32::new(rand::random(), rand::random()));
+}
+
+// SplitMix64 finalizer, used to turn structured keys into unrelated seeds
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Reseeds the calling thread's generator, e.g. before building a scene.
+pub fn seed_thread(seed: u64) {
+    RNG.set(Pcg32::new(mix(seed), mix(!seed)));
+}
+
+/// Switches the calling thread to the random stream of one pixel sample.
+/// The stream depends only on the arguments, so a sample draws the same
+/// numbers no matter which thread renders it or in what order.
+pub fn seed_sample(seed: u64, pixel: u64, sample: u64) {
+    let key = mix(seed ^ mix(pixel ^ mix(sample)));
+    RNG.set(Pcg32::new(key, mix(key ^ pixel)));
+}
+
 pub fn random_double() -> f64 {
     // [0.0, 1.0)
-    rand::rng().random()
+    RNG.with_borrow_mut(|rng| rng.next_f64())
 }
 
 pub fn random_double_range(min: f64, max: f64) -> f64 {
@@ -0,0 +1,429 @@
+use crate::color::{self, Color};
+use crate::colorspace::{self, ColorSpace};
+use crate::common;
+use crate::display;
+use crate::image::Image;
+
+/// Viewing conditions FLIP assumes by default: a 0.7m wide 4K monitor seen
+/// from 0.7m away.
+const PIXELS_PER_DEGREE: f64 = 0.7 * (3840.0 / 0.7) * common::PI / 180.0;
+
+/// How far `test` is from `reference`. Images must be the same size.
+pub struct Metrics {
+    pub rmse: f64,
+    // Squared error relative to the squared reference value, which weighs
+    // errors in dark areas as much as in bright ones
+    pub rel_mse: f64,
+    // Peak signal-to-noise ratio of the sRGB-encoded images, in dB
+    pub psnr: f64,
+    // Mean structural similarity of the sRGB-encoded luma; 1 is identical
+    pub ssim: f64,
+    // Mean FLIP error; 0 is identical, 1 the largest difference
+    pub flip: f64,
+}
+
+impl Metrics {
+    /// Compares the images and returns the metrics together with the
+    /// per-pixel FLIP error, row by row from the top.
+    pub fn compute(reference: &Image, test: &Image) -> (Metrics, Vec<f64>) {
+        let flip = flip(reference, test);
+        let metrics = Metrics {
+            rmse: mean_over_channels(reference, test, |r, t| (t - r).powi(2)).sqrt(),
+            rel_mse: mean_over_channels(reference, test, |r, t| (t - r).powi(2) / (r * r + 0.01)),
+            psnr: psnr(reference, test),
+            ssim: ssim(reference, test),
+            flip: flip.iter().sum::<f64>() / flip.len().max(1) as f64,
+        };
+        (metrics, flip)
+    }
+}
+
+fn mean_over_channels(reference: &Image, test: &Image, error: impl Fn(f64, f64) -> f64) -> f64 {
+    let (r, t) = (pixels(reference), pixels(test));
+    let sum: f64 = r
+        .iter()
+        .zip(&t)
+        .map(|(r, t)| error(r.x(), t.x()) + error(r.y(), t.y()) + error(r.z(), t.z()))
+        .sum();
+    sum / (3 * r.len()).max(1) as f64
+}
+
+fn psnr(reference: &Image, test: &Image) -> f64 {
+    let encode = |v: f64| display::srgb_encode(v.clamp(0.0, 1.0));
+    let mse = mean_over_channels(reference, test, |r, t| (encode(t) - encode(r)).powi(2));
+    -10.0 * mse.log10()
+}
+
+/// SSIM after Wang et al., with an 11 by 11 Gaussian window of standard
+/// deviation 1.5 pixels.
+fn ssim(reference: &Image, test: &Image) -> f64 {
+    const C1: f64 = 0.01 * 0.01;
+    const C2: f64 = 0.03 * 0.03;
+    let luma = |image: &Image| -> Vec<f64> {
+        pixels(image)
+            .iter()
+            .map(|c| color::luminance(map(*c, |v| display::srgb_encode(v.clamp(0.0, 1.0)))))
+            .collect()
+    };
+    let (x, y) = (luma(reference), luma(test));
+    let (width, height) = (reference.width(), reference.height());
+    let window = gaussian_kernel(1.5, 5);
+    let blur = |values: &[f64]| convolve(values, width, height, &window, &window);
+
+    let xx: Vec<f64> = x.iter().map(|v| v * v).collect();
+    let yy: Vec<f64> = y.iter().map(|v| v * v).collect();
+    let xy: Vec<f64> = x.iter().zip(&y).map(|(a, b)| a * b).collect();
+    let (mx, my) = (blur(&x), blur(&y));
+    let (bxx, byy, bxy) = (blur(&xx), blur(&yy), blur(&xy));
+
+    let sum: f64 = (0..x.len())
+        .map(|i| {
+            let (vx, vy) = (bxx[i] - mx[i] * mx[i], byy[i] - my[i] * my[i]);
+            let cov = bxy[i] - mx[i] * my[i];
+            ((2.0 * mx[i] * my[i] + C1) * (2.0 * cov + C2))
+                / ((mx[i] * mx[i] + my[i] * my[i] + C1) * (vx + vy + C2))
+        })
+        .sum();
+    sum / x.len().max(1) as f64
+}
+
+/// Per-pixel LDR-FLIP error (Andersson et al., "FLIP: A Difference
+/// Evaluator for Alternating Images", 2020), computed on the images clamped
+/// to [0, 1]. A color difference of the images filtered like the eye sees
+/// them is raised to a power that grows with the difference in edges and
+/// points.
+pub fn flip(reference: &Image, test: &Image) -> Vec<f64> {
+    const QC: f64 = 0.7;
+    const QF: f64 = 0.5;
+    const PC: f64 = 0.4;
+    const PT: f64 = 0.95;
+
+    let reference = opponent(reference);
+    let test = opponent(test);
+    let (width, height) = (reference.width(), reference.height());
+
+    // Color pipeline
+    let lab = |image: &Image| -> Vec<Color> {
+        let filtered = csf_filter(image);
+        pixels(&filtered)
+            .iter()
+            .map(|c| hunt(to_lab(map(from_opponent(*c), |v| v.clamp(0.0, 1.0)))))
+            .collect()
+    };
+    let (lab_reference, lab_test) = (lab(&reference), lab(&test));
+    let green = hunt(to_lab(Color::new(0.0, 1.0, 0.0)));
+    let blue = hunt(to_lab(Color::new(0.0, 0.0, 1.0)));
+    let cmax = hyab(green, blue).powf(QC);
+    let pccmax = PC * cmax;
+    let color_error = lab_reference.iter().zip(&lab_test).map(|(r, t)| {
+        // Small differences take most of the range, large ones the rest
+        let e = hyab(*r, *t).powf(QC);
+        if e < pccmax {
+            PT / pccmax * e
+        } else {
+            PT + (e - pccmax) / (cmax - pccmax) * (1.0 - PT)
+        }
+    });
+
+    // Feature pipeline, on luminance normalized to the white point
+    let luminance = |image: &Image| -> Vec<f64> {
+        pixels(image)
+            .iter()
+            .map(|c| (c.x() + 16.0) / 116.0)
+            .collect()
+    };
+    let (y_reference, y_test) = (luminance(&reference), luminance(&test));
+    let sd = 0.5 * 0.082 * PIXELS_PER_DEGREE;
+    let radius = (3.0 * sd).ceil() as i64;
+    let gaussian: Vec<f64> = (-radius..=radius)
+        .map(|x| f64::exp(-((x * x) as f64) / (2.0 * sd * sd)))
+        .collect();
+    let edge = feature_kernel(&gaussian, |x, g| -x * g);
+    let point = feature_kernel(&gaussian, |x, g| (x * x / (sd * sd) - 1.0) * g);
+    let features = |values: &[f64], kernel: &[f64]| -> Vec<f64> {
+        let dx = convolve(values, width, height, kernel, &gaussian);
+        let dy = convolve(values, width, height, &gaussian, kernel);
+        dx.iter().zip(&dy).map(|(x, y)| x.hypot(*y)).collect()
+    };
+    let (edges_reference, edges_test) = (features(&y_reference, &edge), features(&y_test, &edge));
+    let (points_reference, points_test) =
+        (features(&y_reference, &point), features(&y_test, &point));
+
+    color_error
+        .enumerate()
+        .map(|(i, color_error)| {
+            let feature_error = f64::max(
+                (edges_reference[i] - edges_test[i]).abs(),
+                (points_reference[i] - points_test[i]).abs(),
+            );
+            let feature_error = (feature_error / std::f64::consts::SQRT_2).powf(QF);
+            color_error.powf(1.0 - feature_error)
+        })
+        .collect()
+}
+
+// Linear RGB to FLIP's YyCxCz opponent space, from CIELab without the
+// nonlinearity
+fn opponent(image: &Image) -> Image {
+    let white = to_xyz(Color::new(1.0, 1.0, 1.0));
+    let mut result = Image::new(image.width(), image.height());
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            let c = to_xyz(map(image.pixel(x, y), |v| v.clamp(0.0, 1.0)));
+            let (cx, cy, cz) = (c.x() / white.x(), c.y() / white.y(), c.z() / white.z());
+            let yy = 116.0 * cy - 16.0;
+            result.set_pixel(x, y, Color::new(yy, 500.0 * (cx - cy), 200.0 * (cy - cz)));
+        }
+    }
+    result
+}
+
+fn from_opponent(c: Color) -> Color {
+    let white = to_xyz(Color::new(1.0, 1.0, 1.0));
+    let y = (c.x() + 16.0) / 116.0;
+    let xyz = Color::new(
+        (c.y() / 500.0 + y) * white.x(),
+        y * white.y(),
+        (y - c.z() / 200.0) * white.z(),
+    );
+    colorspace::transform(&ColorSpace::Rec709.xyz_to_rgb(), xyz)
+}
+
+fn to_xyz(c: Color) -> Color {
+    colorspace::transform(&ColorSpace::Rec709.rgb_to_xyz(), c)
+}
+
+fn to_lab(c: Color) -> Color {
+    const DELTA: f64 = 6.0 / 29.0;
+    let white = to_xyz(Color::new(1.0, 1.0, 1.0));
+    let xyz = to_xyz(c);
+    let f = |t: f64| {
+        if t > DELTA * DELTA * DELTA {
+            t.cbrt()
+        } else {
+            t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+        }
+    };
+    let (fx, fy, fz) = (
+        f(xyz.x() / white.x()),
+        f(xyz.y() / white.y()),
+        f(xyz.z() / white.z()),
+    );
+    Color::new(116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
+
+// Darker colors look less saturated
+fn hunt(lab: Color) -> Color {
+    Color::new(lab.x(), 0.01 * lab.x() * lab.y(), 0.01 * lab.x() * lab.z())
+}
+
+fn hyab(a: Color, b: Color) -> f64 {
+    let d = a - b;
+    d.x().abs() + d.y().hypot(d.z())
+}
+
+// Filters each opponent channel with the eye's contrast sensitivity, a sum of
+// Gaussians per channel
+fn csf_filter(image: &Image) -> Image {
+    // (a1, b1, a2, b2) for the achromatic, red-green and blue-yellow channels
+    const CSF: [[f64; 4]; 3] = [
+        [1.0, 0.0047, 0.0, 1.0e-5],
+        [1.0, 0.0053, 0.0, 1.0e-5],
+        [34.1, 0.04, 13.5, 0.025],
+    ];
+    let largest_b = 0.04;
+    let radius = (3.0 * f64::sqrt(largest_b / (2.0 * common::PI * common::PI)) * PIXELS_PER_DEGREE)
+        .ceil() as i64;
+    let (width, height) = (image.width(), image.height());
+    let channels = pixels(image);
+
+    let mut filtered = [
+        vec![0.0; width * height],
+        vec![0.0; width * height],
+        vec![0.0; width * height],
+    ];
+    for (channel, [a1, b1, a2, b2]) in CSF.iter().enumerate() {
+        let values: Vec<f64> = channels
+            .iter()
+            .map(|c| [c.x(), c.y(), c.z()][channel])
+            .collect();
+        // Each Gaussian is separable; normalize their sum over the 2D kernel
+        let mut total = 0.0;
+        let mut terms = Vec::new();
+        for (a, b) in [(*a1, *b1), (*a2, *b2)] {
+            if a == 0.0 {
+                continue;
+            }
+            let kernel: Vec<f64> = (-radius..=radius)
+                .map(|x| {
+                    let d = x as f64 / PIXELS_PER_DEGREE;
+                    f64::exp(-common::PI * common::PI * d * d / b)
+                })
+                .collect();
+            let weight = a * f64::sqrt(common::PI / b);
+            total += weight * kernel.iter().sum::<f64>().powi(2);
+            terms.push((weight, convolve(&values, width, height, &kernel, &kernel)));
+        }
+        for (weight, term) in terms {
+            for (out, v) in filtered[channel].iter_mut().zip(term) {
+                *out += weight * v / total;
+            }
+        }
+    }
+
+    let [y, cx, cz] = filtered;
+    let mut result = Image::new(width, height);
+    for (i, ((y, cx), cz)) in y.into_iter().zip(cx).zip(cz).enumerate() {
+        result.set_pixel(i % width, i / width, Color::new(y, cx, cz));
+    }
+    result
+}
+
+// The 1D factor of an edge or point detector, `shape(x, gaussian(x))`,
+// scaled so that the 2D kernel's positive weights sum to 1 and its negative
+// ones to -1
+fn feature_kernel(gaussian: &[f64], shape: impl Fn(f64, f64) -> f64) -> Vec<f64> {
+    let radius = (gaussian.len() / 2) as f64;
+    let raw: Vec<f64> = gaussian
+        .iter()
+        .enumerate()
+        .map(|(i, g)| shape(i as f64 - radius, *g))
+        .collect();
+    let across: f64 = gaussian.iter().sum();
+    let positive: f64 = raw.iter().filter(|v| **v > 0.0).sum::<f64>() * across;
+    let negative: f64 = -raw.iter().filter(|v| **v < 0.0).sum::<f64>() * across;
+    raw.iter()
+        .map(|v| if *v > 0.0 { v / positive } else { v / negative })
+        .collect()
+}
+
+fn gaussian_kernel(sigma: f64, radius: i64) -> Vec<f64> {
+    let kernel: Vec<f64> = (-radius..=radius)
+        .map(|x| f64::exp(-((x * x) as f64) / (2.0 * sigma * sigma)))
+        .collect();
+    let sum: f64 = kernel.iter().sum();
+    kernel.iter().map(|v| v / sum).collect()
+}
+
+// Separable filter, `horizontal` along rows and `vertical` along columns,
+// repeating the edge pixels beyond the image
+fn convolve(
+    values: &[f64],
+    width: usize,
+    height: usize,
+    horizontal: &[f64],
+    vertical: &[f64],
+) -> Vec<f64> {
+    let pass = |input: &[f64], kernel: &[f64], along_rows: bool| -> Vec<f64> {
+        let radius = (kernel.len() / 2) as i64;
+        (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as i64, (i / width) as i64);
+                kernel
+                    .iter()
+                    .enumerate()
+                    .map(|(k, w)| {
+                        let offset = k as i64 - radius;
+                        let (nx, ny) = if along_rows {
+                            ((x + offset).clamp(0, width as i64 - 1), y)
+                        } else {
+                            (x, (y + offset).clamp(0, height as i64 - 1))
+                        };
+                        w * input[ny as usize * width + nx as usize]
+                    })
+                    .sum()
+            })
+            .collect()
+    };
+    pass(&pass(values, horizontal, true), vertical, false)
+}
+
+fn pixels(image: &Image) -> Vec<Color> {
+    (0..image.height())
+        .flat_map(|y| (0..image.width()).map(move |x| image.pixel(x, y)))
+        .collect()
+}
+
+fn map(c: Color, f: impl Fn(f64) -> f64) -> Color {
+    Color::new(f(c.x()), f(c.y()), f(c.z()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flat(width: usize, height: usize, c: Color) -> Image {
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                image.set_pixel(x, y, c);
+            }
+        }
+        image
+    }
+
+    fn checker(width: usize, height: usize) -> Image {
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let v = if (x / 4 + y / 4) % 2 == 0 { 0.8 } else { 0.1 };
+                image.set_pixel(x, y, Color::new(v, 0.5 * v, 0.2));
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn identical_images_have_no_error() {
+        let image = checker(32, 24);
+        let (metrics, map) = Metrics::compute(&image, &image);
+        assert_eq!(metrics.rmse, 0.0);
+        assert_eq!(metrics.rel_mse, 0.0);
+        assert_eq!(metrics.psnr, f64::INFINITY);
+        assert!((metrics.ssim - 1.0).abs() < 1.0e-9);
+        assert!(map.iter().all(|e| *e == 0.0));
+    }
+
+    #[test]
+    fn errors_grow_with_the_difference() {
+        let reference = flat(16, 16, Color::new(0.5, 0.5, 0.5));
+        let near = flat(16, 16, Color::new(0.52, 0.5, 0.5));
+        let far = flat(16, 16, Color::new(0.9, 0.1, 0.5));
+        let (near, _) = Metrics::compute(&reference, &near);
+        let (far, _) = Metrics::compute(&reference, &far);
+        assert!((near.rmse - f64::sqrt(0.02 * 0.02 / 3.0)).abs() < 1.0e-12);
+        assert!(near.psnr > far.psnr);
+        assert!(near.flip > 0.0 && near.flip < far.flip);
+        assert!(near.ssim > far.ssim);
+    }
+
+    #[test]
+    fn flip_of_black_against_white_matches_the_paper() {
+        // The color error alone, as flat images have no features:
+        // HyAB of 100 compressed to 100^0.7, then mapped above pc * cmax
+        let black = flat(8, 8, Color::new(0.0, 0.0, 0.0));
+        let white = flat(8, 8, Color::new(1.0, 1.0, 1.0));
+        let map = flip(&black, &white);
+        assert!((map[0] - 0.967).abs() < 0.002, "{}", map[0]);
+    }
+
+    #[test]
+    fn ssim_sees_structure_not_just_brightness() {
+        let reference = checker(32, 32);
+        let blurred = {
+            let mut image = reference.clone();
+            for y in 0..32 {
+                for x in 1..31 {
+                    let avg = (reference.pixel(x - 1, y) + reference.pixel(x + 1, y)) / 2.0;
+                    image.set_pixel(x, y, avg);
+                }
+            }
+            image
+        };
+        let black = flat(32, 32, Color::new(0.0, 0.0, 0.0));
+        let (blur, _) = Metrics::compute(&reference, &blurred);
+        let (dark, _) = Metrics::compute(&reference, &black);
+        assert!(blur.ssim < 0.99);
+        assert!(dark.ssim < blur.ssim);
+    }
+}
@@ -0,0 +1,238 @@
+use rayon::prelude::*;
+
+use crate::color::{self, Color};
+use crate::image::Image;
+use crate::vec3;
+
+// Passes of the à-trous filter; the last one reaches 2^(n-1) * 2 pixels out
+const ITERATIONS: u32 = 5;
+// B3 spline taps; the 5x5 kernel is their outer product, spread 2^i pixels apart
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+// Edge-stopping strengths. Smaller sigmas and larger powers preserve more
+// detail. Luminance differences are measured in standard deviations.
+const SIGMA_COLOR: f64 = 4.0;
+const SIGMA_ALBEDO: f64 = 0.1;
+const NORMAL_POWER: i32 = 64;
+// Allowed relative depth change per pixel of distance
+const SIGMA_DEPTH: f64 = 0.01;
+
+/// Feature buffers gathered at the first hit, as resolved from the AOVs.
+pub struct Features<'a> {
+    pub albedo: &'a Image,
+    pub normal: &'a Image,
+    pub depth: &'a Image,
+    // Variance of each pixel's mean luminance, row by row
+    pub variance: &'a [f64],
+}
+
+/// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010) with
+/// variance-guided color weights as in SVGF. The image is divided by the
+/// albedo so that textures are not blurred, filtered with weights that fall
+/// off across changes in luminance, albedo, normal and depth, and multiplied
+/// by the albedo again. Silhouettes between objects show up as depth or
+/// normal discontinuities and stay sharp. Reflections and refractions only
+/// have the features of the mirror or glass itself and are smoothed.
+pub fn denoise(image: &Image, features: &Features) -> Image {
+    let (width, height) = (image.width(), image.height());
+    let albedo: Vec<Color> = pixels(features.albedo)
+        .into_iter()
+        .map(|a| Color::new(demodulator(a.x()), demodulator(a.y()), demodulator(a.z())))
+        .collect();
+    let normal = pixels(features.normal);
+    let depth: Vec<f64> = pixels(features.depth).iter().map(|d| d.x()).collect();
+
+    let mut irradiance: Vec<Color> = pixels(image)
+        .iter()
+        .zip(&albedo)
+        .map(|(c, a)| divide(*c, *a))
+        .collect();
+    // Variance of the demodulated luminance. Pixels with a single sample
+    // have no estimate and are assumed to be as noisy as they are bright.
+    let mut variance: Vec<f64> = features
+        .variance
+        .iter()
+        .zip(&albedo)
+        .zip(&irradiance)
+        .map(|((v, a), c)| {
+            if v.is_finite() {
+                v / color::luminance(*a).powi(2)
+            } else {
+                color::luminance(*c).powi(2)
+            }
+        })
+        .collect();
+
+    for iteration in 0..ITERATIONS {
+        let step = 1i64 << iteration;
+        let input = &irradiance;
+        let input_variance = &variance;
+        let blurred_variance = blur_3x3(input_variance, width, height);
+
+        let filtered: Vec<(Color, f64)> = (0..width * height)
+            .into_par_iter()
+            .map(|i| {
+                let (x, y) = ((i % width) as i64, (i / width) as i64);
+                let luminance = color::luminance(input[i]);
+                let color_scale = SIGMA_COLOR * blurred_variance[i].sqrt() + 1.0e-6;
+                let mut sum = Color::default();
+                let mut variance_sum = 0.0;
+                let mut weight_sum = 0.0;
+
+                for (ky, wy) in KERNEL.iter().enumerate() {
+                    for (kx, wx) in KERNEL.iter().enumerate() {
+                        let nx = x + (kx as i64 - 2) * step;
+                        let ny = y + (ky as i64 - 2) * step;
+                        if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                            continue;
+                        }
+                        let j = ny as usize * width + nx as usize;
+
+                        let distance = ((nx - x).abs().max((ny - y).abs())) as f64;
+                        let w_color =
+                            f64::exp(-(luminance - color::luminance(input[j])).abs() / color_scale);
+                        let w_albedo = f64::exp(
+                            -(albedo[i] - albedo[j]).length_squared()
+                                / (SIGMA_ALBEDO * SIGMA_ALBEDO),
+                        );
+                        let w_normal = if normal[i].near_zero() && normal[j].near_zero() {
+                            1.0
+                        } else {
+                            vec3::dot(normal[i], normal[j]).max(0.0).powi(NORMAL_POWER)
+                        };
+                        let w_depth = f64::exp(
+                            -(depth[i] - depth[j]).abs()
+                                / (SIGMA_DEPTH * depth[i].max(1.0e-3) * distance.max(1.0)),
+                        );
+
+                        let weight = wx * wy * w_color * w_albedo * w_normal * w_depth;
+                        sum += weight * input[j];
+                        variance_sum += weight * weight * input_variance[j];
+                        weight_sum += weight;
+                    }
+                }
+
+                // The center tap always has a positive weight
+                (sum / weight_sum, variance_sum / (weight_sum * weight_sum))
+            })
+            .collect();
+
+        irradiance = filtered.iter().map(|(c, _)| *c).collect();
+        variance = filtered.iter().map(|(_, v)| *v).collect();
+    }
+
+    let mut result = Image::new(width, height);
+    for y in 0..height {
+        for x in 0..width {
+            let i = y * width + x;
+            result.set_pixel(x, y, irradiance[i] * albedo[i]);
+        }
+    }
+    result
+}
+
+fn blur_3x3(values: &[f64], width: usize, height: usize) -> Vec<f64> {
+    const WEIGHTS: [f64; 3] = [0.25, 0.5, 0.25];
+    (0..width * height)
+        .map(|i| {
+            let (x, y) = ((i % width) as i64, (i / width) as i64);
+            let mut sum = 0.0;
+            let mut weight_sum = 0.0;
+            for (dy, wy) in (-1..=1).zip(WEIGHTS) {
+                for (dx, wx) in (-1..=1).zip(WEIGHTS) {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                        continue;
+                    }
+                    sum += wx * wy * values[ny as usize * width + nx as usize];
+                    weight_sum += wx * wy;
+                }
+            }
+            sum / weight_sum
+        })
+        .collect()
+}
+
+fn pixels(image: &Image) -> Vec<Color> {
+    (0..image.height())
+        .flat_map(|y| (0..image.width()).map(move |x| image.pixel(x, y)))
+        .collect()
+}
+
+// Dark or missing albedo is left in the image rather than divided out
+fn demodulator(albedo: f64) -> f64 {
+    if albedo < 0.01 { 1.0 } else { albedo }
+}
+
+fn divide(c: Color, d: Color) -> Color {
+    Color::new(c.x() / d.x(), c.y() / d.y(), c.z() / d.z())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common;
+
+    #[test]
+    fn smooths_noise_but_keeps_edges() {
+        // Two surfaces meeting in a vertical edge, facing different ways and
+        // at different depths. Their brightness differs by less than the
+        // noise, so only the features can keep them apart.
+        let (width, height) = (32, 32);
+        let side = |x: usize| x >= width / 2;
+        let mut image = Image::new(width, height);
+        let mut albedo = Image::new(width, height);
+        let mut normal = Image::new(width, height);
+        let mut depth = Image::new(width, height);
+        common::seed_thread(1);
+        for y in 0..height {
+            for x in 0..width {
+                let (value, n, d) = if side(x) {
+                    (0.6, Color::new(1.0, 0.0, 0.0), 5.0)
+                } else {
+                    (0.4, Color::new(0.0, 0.0, 1.0), 2.0)
+                };
+                let v = value + common::random_double_range(-0.2, 0.2);
+                image.set_pixel(x, y, Color::new(v, v, v));
+                albedo.set_pixel(x, y, Color::new(1.0, 1.0, 1.0));
+                normal.set_pixel(x, y, n);
+                depth.set_pixel(x, y, Color::new(d, d, d));
+            }
+        }
+        // Of uniform noise 0.4 wide
+        let variance = vec![0.4 * 0.4 / 12.0; width * height];
+        let features = Features {
+            albedo: &albedo,
+            normal: &normal,
+            depth: &depth,
+            variance: &variance,
+        };
+        let result = denoise(&image, &features);
+
+        let stats = |image: &Image, columns: &[usize]| {
+            let values: Vec<f64> = columns
+                .iter()
+                .flat_map(|x| (0..height).map(move |y| (*x, y)))
+                .map(|(x, y)| image.pixel(x, y).y())
+                .collect();
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            let variance =
+                values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+            (mean, variance)
+        };
+        for columns in [
+            (0..width / 2).collect::<Vec<_>>(),
+            (width / 2..width).collect(),
+        ] {
+            let (_, before) = stats(&image, &columns);
+            let (_, after) = stats(&result, &columns);
+            assert!(after < 0.1 * before, "{after} vs {before}");
+        }
+
+        // The columns either side of the edge keep their own level
+        let (left, _) = stats(&result, &[width / 2 - 1]);
+        let (right, _) = stats(&result, &[width / 2]);
+        assert!((left - 0.4).abs() < 0.02, "{left}");
+        assert!((right - 0.6).abs() < 0.02, "{right}");
+    }
+}
@@ -0,0 +1,145 @@
+use std::sync::Arc;
+
+use crate::color::Color;
+use crate::colorspace::{self, ColorSpace, Matrix3};
+use crate::lut::Lut;
+
+/// Curve that compresses scene radiance into the displayable range.
+#[derive(Clone, Copy, Default)]
+pub enum ToneMap {
+    // Values above 1 are cut off
+    #[default]
+    Clip,
+    // x / (1 + x) per channel
+    Reinhard,
+    // John Hable's filmic curve from Uncharted 2
+    Hable,
+    // Stephen Hill's fit of the ACES reference and output transforms
+    Aces,
+}
+
+/// Turns linear scene radiance into display-referred values: conversion to
+/// Rec.709, exposure compensation, tone mapping, sRGB encoding, then an
+/// optional grading LUT.
+#[derive(Clone, Default)]
+pub struct DisplayTransform {
+    // Exposure compensation in stops
+    pub exposure: f64,
+    pub tone_map: ToneMap,
+    // Space the radiance is given in
+    pub color_space: ColorSpace,
+    // Applied to the encoded values, which is what grading LUTs expect
+    pub lut: Option<Arc<Lut>>,
+}
+
+impl DisplayTransform {
+    /// The sRGB-encoded display color for `radiance`, in [0, 1].
+    pub fn apply(&self, radiance: Color) -> Color {
+        let c = self.color_space.convert(radiance, ColorSpace::Rec709);
+        let c = f64::powf(2.0, self.exposure) * c;
+        let mapped = match self.tone_map {
+            ToneMap::Clip => c,
+            ToneMap::Reinhard => map_channels(c, |x| x / (1.0 + x)),
+            ToneMap::Hable => {
+                const WHITE: f64 = 11.2;
+                // The curve is made for radiance exposed up by a stop
+                map_channels(c, |x| hable(2.0 * x) / hable(WHITE))
+            }
+            ToneMap::Aces => aces(c),
+        };
+        let encoded = map_channels(mapped, |x| srgb_encode(x.clamp(0.0, 1.0)));
+        match &self.lut {
+            Some(lut) => map_channels(lut.apply(encoded), |x| x.clamp(0.0, 1.0)),
+            None => encoded,
+        }
+    }
+}
+
+/// The sRGB opto-electronic transfer function.
+pub fn srgb_encode(linear: f64) -> f64 {
+    if linear <= 0.0031308 {
+        12.92 * linear
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Inverse of `srgb_encode`, for images that hold display colors.
+pub fn srgb_decode(encoded: f64) -> f64 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn map_channels(c: Color, f: impl Fn(f64) -> f64) -> Color {
+    Color::new(f(c.x()), f(c.y()), f(c.z()))
+}
+
+fn hable(x: f64) -> f64 {
+    const A: f64 = 0.15; // shoulder strength
+    const B: f64 = 0.50; // linear strength
+    const C: f64 = 0.10; // linear angle
+    const D: f64 = 0.20; // toe strength
+    const E: f64 = 0.02; // toe numerator
+    const F: f64 = 0.30; // toe denominator
+    (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F
+}
+
+fn aces(c: Color) -> Color {
+    // sRGB to the ACES reference input space, with the RRT's saturation
+    const INPUT: Matrix3 = [
+        [0.59719, 0.35458, 0.04823],
+        [0.07600, 0.90834, 0.01566],
+        [0.02840, 0.13383, 0.83777],
+    ];
+    // Output transform back to sRGB
+    const OUTPUT: Matrix3 = [
+        [1.60475, -0.53108, -0.07367],
+        [-0.10208, 1.10813, -0.00605],
+        [-0.00327, -0.07276, 1.07602],
+    ];
+
+    let c = colorspace::transform(&INPUT, c);
+    let c = map_channels(c, |v| {
+        let a = v * (v + 0.0245786) - 0.000090537;
+        let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+        a / b
+    });
+    colorspace::transform(&OUTPUT, c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_round_trips() {
+        for i in 0..=100 {
+            let v = i as f64 / 100.0;
+            assert!((srgb_decode(srgb_encode(v)) - v).abs() < 1.0e-12);
+        }
+        assert!((srgb_encode(0.18) - 0.4614).abs() < 1.0e-4);
+    }
+
+    #[test]
+    fn tone_maps_are_monotonic_and_bounded() {
+        for tone_map in [ToneMap::Reinhard, ToneMap::Hable, ToneMap::Aces] {
+            let display = DisplayTransform {
+                exposure: 0.0,
+                tone_map,
+                ..Default::default()
+            };
+            let mut previous = 0.0;
+            for i in 0..200 {
+                let x = 0.05 * i as f64;
+                let y = display.apply(Color::new(x, x, x)).y();
+                assert!(y >= previous && y <= 1.0);
+                previous = y;
+            }
+            // Bright values keep some headroom rather than clipping early
+            assert!(display.apply(Color::new(2.0, 2.0, 2.0)).y() < 1.0);
+        }
+    }
+}
@@ -0,0 +1,423 @@
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+
+use crate::aov::{AovPixel, Aovs};
+use crate::color::{self, Color};
+use crate::filter::Filter;
+use crate::image::Image;
+use crate::tile::Tile;
+
+const CHECKPOINT_MAGIC: &[u8; 8] = b"RTFILM03";
+const RECORD_SIZE: usize = 72;
+
+/// Radiance samples taken for one pixel during a pass.
+#[derive(Clone, Copy, Default)]
+pub struct PixelSamples {
+    pub sum: Color,
+    // Sum of squared sample luminance, for the variance estimate
+    pub sum_sq: f64,
+    pub count: u64,
+}
+
+impl PixelSamples {
+    pub fn push(&mut self, sample: Color) {
+        let l = color::luminance(sample);
+        self.sum += sample;
+        self.sum_sq += l * l;
+        self.count += 1;
+    }
+}
+
+/// Filter-weighted radiance splatted by the samples of one tile. It covers
+/// the tile plus the filter's reach on every side, since samples spill into
+/// neighbouring pixels.
+pub struct Splats {
+    filter: Filter,
+    x0: i64,
+    y0: i64,
+    width: usize,
+    height: usize,
+    values: Vec<Color>,
+    weights: Vec<f64>,
+}
+
+impl Splats {
+    pub fn new(tile: &Tile, filter: Filter) -> Self {
+        let margin = (filter.radius() - 0.5).ceil() as usize;
+        let width = tile.x1 - tile.x0 + 2 * margin;
+        let height = tile.y1 - tile.y0 + 2 * margin;
+        Splats {
+            filter,
+            x0: tile.x0 as i64 - margin as i64,
+            y0: tile.y0 as i64 - margin as i64,
+            width,
+            height,
+            values: vec![Color::default(); width * height],
+            weights: vec![0.0; width * height],
+        }
+    }
+
+    /// Adds a sample taken at the continuous image position (px, py), where
+    /// pixel (x, y) has its center at (x + 0.5, y + 0.5). Only pixels in
+    /// `region` receive it, so that separate views in one image, such as
+    /// the eyes of a stereo pair, do not bleed into each other.
+    pub fn add_sample(&mut self, px: f64, py: f64, sample: Color, region: &Tile) {
+        let r = self.filter.radius();
+        let x_min = ((px - 0.5 - r).ceil() as i64)
+            .max(self.x0)
+            .max(region.x0 as i64);
+        let x_max = ((px - 0.5 + r).floor() as i64)
+            .min(self.x0 + self.width as i64 - 1)
+            .min(region.x1 as i64 - 1);
+        let y_min = ((py - 0.5 - r).ceil() as i64)
+            .max(self.y0)
+            .max(region.y0 as i64);
+        let y_max = ((py - 0.5 + r).floor() as i64)
+            .min(self.y0 + self.height as i64 - 1)
+            .min(region.y1 as i64 - 1);
+
+        for y in y_min..=y_max {
+            for x in x_min..=x_max {
+                let weight = self
+                    .filter
+                    .evaluate(px - (x as f64 + 0.5), py - (y as f64 + 0.5));
+                if weight == 0.0 {
+                    continue;
+                }
+                let i = (y - self.y0) as usize * self.width + (x - self.x0) as usize;
+                self.values[i] += weight * sample;
+                self.weights[i] += weight;
+            }
+        }
+    }
+}
+
+/// Running per-pixel sums of radiance samples and how many samples went
+/// into each, so that renders can be continued and resolved at any time.
+/// Alongside the plain per-pixel statistics, which drive adaptive sampling,
+/// it accumulates the filter-weighted splats that make up the final image.
+/// AOVs are only gathered on request and are not part of checkpoints.
+#[derive(Clone)]
+pub struct Film {
+    width: usize,
+    height: usize,
+    pixels: Vec<PixelSamples>,
+    filtered: Vec<Color>,
+    weights: Vec<f64>,
+    aovs: Option<Aovs>,
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Self {
+        Film {
+            width,
+            height,
+            pixels: vec![PixelSamples::default(); width * height],
+            filtered: vec![Color::default(); width * height],
+            weights: vec![0.0; width * height],
+            aovs: None,
+        }
+    }
+
+    /// Also gathers AOVs from the samples added from now on.
+    pub fn with_aovs(mut self) -> Self {
+        self.aovs = Some(Aovs::new(self.width, self.height));
+        self
+    }
+
+    pub fn aovs(&self) -> Option<&Aovs> {
+        self.aovs.as_ref()
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn add(&mut self, x: usize, y: usize, samples: &PixelSamples) {
+        let pixel = &mut self.pixels[y * self.width + x];
+        pixel.sum += samples.sum;
+        pixel.sum_sq += samples.sum_sq;
+        pixel.count += samples.count;
+    }
+
+    pub fn add_aovs(&mut self, x: usize, y: usize, pixel: &AovPixel) {
+        if let Some(aovs) = &mut self.aovs {
+            aovs.add(x, y, pixel);
+        }
+    }
+
+    pub fn add_splats(&mut self, splats: &Splats) {
+        for sy in 0..splats.height {
+            let y = splats.y0 + sy as i64;
+            if y < 0 || y >= self.height as i64 {
+                continue;
+            }
+            for sx in 0..splats.width {
+                let x = splats.x0 + sx as i64;
+                if x < 0 || x >= self.width as i64 {
+                    continue;
+                }
+                let i = y as usize * self.width + x as usize;
+                self.filtered[i] += splats.values[sy * splats.width + sx];
+                self.weights[i] += splats.weights[sy * splats.width + sx];
+            }
+        }
+    }
+
+    pub fn samples(&self, x: usize, y: usize) -> u64 {
+        self.pixels[y * self.width + x].count
+    }
+
+    /// Fewest samples taken by any pixel.
+    pub fn min_samples(&self) -> u64 {
+        self.pixels.iter().map(|p| p.count).min().unwrap_or(0)
+    }
+
+    pub fn total_samples(&self) -> u64 {
+        self.pixels.iter().map(|p| p.count).sum()
+    }
+
+    /// Estimated variance of the pixel's mean luminance.
+    pub fn variance(&self, x: usize, y: usize) -> f64 {
+        let pixel = &self.pixels[y * self.width + x];
+        if pixel.count < 2 {
+            return f64::INFINITY;
+        }
+
+        let n = pixel.count as f64;
+        let mean = color::luminance(pixel.sum) / n;
+        ((pixel.sum_sq - n * mean * mean) / (n - 1.0)).max(0.0) / n
+    }
+
+    /// Standard error of the pixel's mean luminance relative to the mean
+    /// itself. Dark pixels are compared against a small floor so that they
+    /// are not refined forever.
+    pub fn relative_error(&self, x: usize, y: usize) -> f64 {
+        let pixel = &self.pixels[y * self.width + x];
+        if pixel.count < 2 {
+            return f64::INFINITY;
+        }
+
+        let mean = color::luminance(pixel.sum) / pixel.count as f64;
+        self.variance(x, y).sqrt() / (mean.abs() + 0.01)
+    }
+
+    /// Filter-weighted average of the splats on every pixel. Pixels with no
+    /// usable filter weight fall back to the plain average of their own
+    /// samples, and pixels without samples are black.
+    pub fn resolve(&self) -> Image {
+        let mut image = Image::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let i = y * self.width + x;
+                let pixel = &self.pixels[i];
+                if self.weights[i].abs() > 1.0e-9 {
+                    image.set_pixel(x, y, self.filtered[i] / self.weights[i]);
+                } else if pixel.count > 0 {
+                    image.set_pixel(x, y, pixel.sum / pixel.count as f64);
+                }
+            }
+        }
+        image
+    }
+
+    /// False-color map of the samples taken per pixel, from dark blue for
+    /// the fewest through green to red for the most.
+    pub fn sample_heatmap(&self) -> Image {
+        let max = self
+            .pixels
+            .iter()
+            .map(|p| p.count)
+            .max()
+            .unwrap_or(0)
+            .max(1) as f64;
+        let mut image = Image::new(self.width, self.height);
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let t = self.samples(x, y) as f64 / max;
+                image.set_pixel(x, y, color::heat(t));
+            }
+        }
+        image
+    }
+
+    /// Writes the accumulated sums and sample counts. The file is written
+    /// next to `path` first and then renamed over it, so an interrupted save
+    /// never destroys the previous checkpoint.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut bytes = Vec::with_capacity(24 + self.pixels.len() * RECORD_SIZE);
+        bytes.extend_from_slice(CHECKPOINT_MAGIC);
+        bytes.extend_from_slice(&(self.width as u64).to_le_bytes());
+        bytes.extend_from_slice(&(self.height as u64).to_le_bytes());
+        for (i, pixel) in self.pixels.iter().enumerate() {
+            for c in [pixel.sum.x(), pixel.sum.y(), pixel.sum.z(), pixel.sum_sq] {
+                bytes.extend_from_slice(&c.to_le_bytes());
+            }
+            bytes.extend_from_slice(&pixel.count.to_le_bytes());
+
+            let filtered = self.filtered[i];
+            for c in [filtered.x(), filtered.y(), filtered.z(), self.weights[i]] {
+                bytes.extend_from_slice(&c.to_le_bytes());
+            }
+        }
+
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, bytes)?;
+        fs::rename(tmp, path)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Film> {
+        let bytes = fs::read(path)?;
+        if bytes.len() < 24 || &bytes[..8] != CHECKPOINT_MAGIC {
+            return Err(invalid_data("not a film checkpoint"));
+        }
+
+        let size = |offset| usize::try_from(read_u64(&bytes, offset)).ok();
+        let (width, height) = size(8)
+            .zip(size(16))
+            .ok_or_else(|| invalid_data("film checkpoint too large"))?;
+        let length = width
+            .checked_mul(height)
+            .and_then(|n| n.checked_mul(RECORD_SIZE))
+            .and_then(|n| n.checked_add(24));
+        if length != Some(bytes.len()) {
+            return Err(invalid_data("truncated film checkpoint"));
+        }
+
+        let mut film = Film::new(width, height);
+        for (i, record) in bytes[24..].chunks_exact(RECORD_SIZE).enumerate() {
+            film.pixels[i] = PixelSamples {
+                sum: Color::new(
+                    read_f64(record, 0),
+                    read_f64(record, 8),
+                    read_f64(record, 16),
+                ),
+                sum_sq: read_f64(record, 24),
+                count: read_u64(record, 32),
+            };
+            film.filtered[i] = Color::new(
+                read_f64(record, 40),
+                read_f64(record, 48),
+                read_f64(record, 56),
+            );
+            film.weights[i] = read_f64(record, 64);
+        }
+        Ok(film)
+    }
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_f64(bytes: &[u8], offset: usize) -> f64 {
+    f64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+fn invalid_data(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::FilterKind;
+    use std::env;
+
+    /// A small film with distinct samples and splats on every pixel.
+    fn sampled_film() -> Film {
+        let mut film = Film::new(3, 2);
+        let tile = Tile {
+            x0: 0,
+            y0: 0,
+            x1: 3,
+            y1: 2,
+        };
+        let mut splats = Splats::new(&tile, Filter::default());
+        for y in 0..2 {
+            for x in 0..3 {
+                let mut samples = PixelSamples::default();
+                for k in 0..=x + y {
+                    let sample = Color::new(0.1 * k as f64, 0.2 * x as f64, 0.3 * y as f64);
+                    samples.push(sample);
+                    splats.add_sample(x as f64 + 0.25, y as f64 + 0.75, sample, &tile);
+                }
+                film.add(x, y, &samples);
+            }
+        }
+        film.add_splats(&splats);
+        film
+    }
+
+    fn checkpoint_path(name: &str) -> std::path::PathBuf {
+        env::temp_dir().join(format!("film-{}-{name}.ckpt", std::process::id()))
+    }
+
+    #[test]
+    fn checkpoints_round_trip() {
+        let film = sampled_film();
+        let path = checkpoint_path("round-trip");
+        film.save(&path).unwrap();
+        let loaded = Film::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!((loaded.width(), loaded.height()), (3, 2));
+        let bits = |c: Color| [c.x().to_bits(), c.y().to_bits(), c.z().to_bits()];
+        for (a, b) in film.pixels.iter().zip(&loaded.pixels) {
+            assert_eq!(bits(a.sum), bits(b.sum));
+            assert_eq!(a.sum_sq.to_bits(), b.sum_sq.to_bits());
+            assert_eq!(a.count, b.count);
+        }
+        for i in 0..film.pixels.len() {
+            assert_eq!(bits(film.filtered[i]), bits(loaded.filtered[i]));
+            assert_eq!(film.weights[i].to_bits(), loaded.weights[i].to_bits());
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_checkpoints() {
+        let path = checkpoint_path("truncated");
+        sampled_film().save(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        for length in [0, 12, 24, bytes.len() - RECORD_SIZE, bytes.len() - 1] {
+            fs::write(&path, &bytes[..length]).unwrap();
+            let err = Film::load(&path).err().unwrap();
+            assert_eq!(err.kind(), ErrorKind::InvalidData, "{length} bytes");
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tent_splats_weigh_neighbours_by_distance() {
+        let tile = Tile {
+            x0: 0,
+            y0: 0,
+            x1: 3,
+            y1: 3,
+        };
+        let mut splats = Splats::new(&tile, Filter::new(FilterKind::Tent, 1.0));
+        // A quarter pixel right of the center of pixel (1, 1)
+        splats.add_sample(1.75, 1.5, Color::new(2.0, 2.0, 2.0), &tile);
+
+        let margin = 1;
+        for y in 0..3 {
+            for x in 0..3 {
+                let expected = match (x, y) {
+                    (1, 1) => 0.75,
+                    (2, 1) => 0.25,
+                    _ => 0.0,
+                };
+                let i = (y + margin) * splats.width + x + margin;
+                assert!((splats.weights[i] - expected).abs() < 1.0e-12, "({x}, {y})");
+                assert!((splats.values[i].x() - 2.0 * expected).abs() < 1.0e-12);
+            }
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use crate::common;
+
+#[derive(Clone, Copy, Default)]
+pub enum FilterKind {
+    // Plain average of the samples inside the radius
+    #[default]
+    Box,
+    Tent,
+    Gaussian,
+    // Mitchell-Netravali with B = C = 1/3
+    Mitchell,
+    // Windowed sinc with as many lobes as the radius spans
+    Lanczos,
+}
+
+impl FilterKind {
+    pub fn default_radius(self) -> f64 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.0,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.0,
+            FilterKind::Lanczos => 3.0,
+        }
+    }
+}
+
+/// Separable pixel reconstruction filter. Each sample contributes to every
+/// pixel whose center lies within `radius` (in pixels) on both axes,
+/// weighted by `evaluate`. Mitchell and Lanczos have negative lobes.
+#[derive(Clone, Copy)]
+pub struct Filter {
+    kind: FilterKind,
+    radius: f64,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::new(
+            FilterKind::default(),
+            FilterKind::default().default_radius(),
+        )
+    }
+}
+
+impl Filter {
+    /// `radius` must be at least 0.5, so that each pixel's own samples
+    /// reach it.
+    pub fn new(kind: FilterKind, radius: f64) -> Self {
+        debug_assert!(radius >= 0.5);
+        Filter { kind, radius }
+    }
+
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    pub fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+        if dx.abs() >= self.radius || dy.abs() >= self.radius {
+            return 0.0;
+        }
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let r = self.radius;
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => r - x.abs(),
+            FilterKind::Gaussian => {
+                let sigma = r / 3.0;
+                let g = |x: f64| f64::exp(-x * x / (2.0 * sigma * sigma));
+                (g(x) - g(r)).max(0.0)
+            }
+            FilterKind::Mitchell => mitchell_1d(2.0 * x / r, 1.0 / 3.0, 1.0 / 3.0),
+            FilterKind::Lanczos => sinc(x) * sinc(x / r),
+        }
+    }
+}
+
+fn mitchell_1d(x: f64, b: f64, c: f64) -> f64 {
+    let x = x.abs();
+    if x <= 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b))
+            / 6.0
+    } else if x <= 2.0 {
+        ((-b - 6.0 * c) * x * x * x
+            + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c))
+            / 6.0
+    } else {
+        0.0
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1.0e-5 {
+        return 1.0;
+    }
+    let px = common::PI * x;
+    f64::sin(px) / px
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [FilterKind; 5] = [
+        FilterKind::Box,
+        FilterKind::Tent,
+        FilterKind::Gaussian,
+        FilterKind::Mitchell,
+        FilterKind::Lanczos,
+    ];
+
+    #[test]
+    fn integrals_are_positive() {
+        for kind in KINDS {
+            for radius in [0.5, kind.default_radius(), 2.5] {
+                let filter = Filter::new(kind, radius);
+                // Midpoint rule over the filter's square
+                let n = 200;
+                let step = 2.0 * radius / n as f64;
+                let at = |i: usize| -radius + (i as f64 + 0.5) * step;
+                let integral: f64 = (0..n)
+                    .flat_map(|i| (0..n).map(move |j| (i, j)))
+                    .map(|(i, j)| filter.evaluate(at(i), at(j)) * step * step)
+                    .sum();
+                assert!(integral > 0.0, "radius {radius}");
+            }
+        }
+    }
+
+    #[test]
+    fn vanish_outside_the_radius() {
+        for kind in [FilterKind::Box, FilterKind::Tent, FilterKind::Gaussian] {
+            let filter = Filter::new(kind, 1.5);
+            for d in [1.5, 1.5001, 2.0, 10.0] {
+                for (dx, dy) in [(d, 0.0), (-d, 0.0), (0.0, d), (0.0, -d), (d, d)] {
+                    assert_eq!(filter.evaluate(dx, dy), 0.0);
+                }
+            }
+            assert!(filter.evaluate(1.4, 0.0) >= 0.0);
+            assert!(filter.evaluate(0.0, 0.0) > 0.0);
+        }
+    }
+}
@@ -1,7 +1,9 @@
 use std::sync::Arc;
 
+use crate::color;
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::texture::Texture;
 use crate::vec3::{self, Point3, Vec3};
 
 #[derive(Clone, Default)]
@@ -10,7 +12,15 @@ pub struct HitRecord {
     pub normal: Vec3,
     pub mat: Option<Arc<dyn Material>>,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
+    // Unit tangent frame along increasing u and v, built around the outward
+    // normal so that it does not flip with `front_face`.
+    pub tangent: Vec3,
+    pub bitangent: Vec3,
     pub front_face: bool,
+    // Index of the hit object in the outermost list, for the object ID layer
+    pub object_id: usize,
 }
 
 impl HitRecord {
@@ -26,8 +36,56 @@ impl HitRecord {
             -outwards_normal
         };
     }
+
+    /// Builds an orthonormal tangent frame from the surface derivative along u.
+    /// Falls back to an arbitrary frame where `dpdu` degenerates (e.g. poles).
+    pub fn set_tangent_frame(&mut self, outwards_normal: Vec3, dpdu: Vec3) {
+        let projected = dpdu - vec3::dot(dpdu, outwards_normal) * outwards_normal;
+        self.tangent = if projected.length_squared() > 1.0e-16 {
+            vec3::unit_vector(projected)
+        } else {
+            let a = if outwards_normal.x().abs() > 0.9 {
+                Vec3::new(0.0, 1.0, 0.0)
+            } else {
+                Vec3::new(1.0, 0.0, 0.0)
+            };
+            vec3::unit_vector(vec3::cross(a, outwards_normal))
+        };
+        self.bitangent = vec3::cross(outwards_normal, self.tangent);
+    }
+
+    /// The geometric normal pointing out of the surface, regardless of which
+    /// side the ray arrived from.
+    pub fn outwards_normal(&self) -> Vec3 {
+        if self.front_face {
+            self.normal
+        } else {
+            -self.normal
+        }
+    }
 }
 
 pub trait Hittable: Send + Sync {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool;
+
+    /// Every material the object's hits can report, in a fixed order.
+    fn materials(&self) -> Vec<&Arc<dyn Material>>;
+}
+
+/// Opacity cutout for foliage cards and decals. Hits where the mask's
+/// luminance falls below the threshold are treated as misses, so every ray
+/// that goes through `Hittable::hit` passes straight through them.
+pub struct AlphaMask {
+    texture: Arc<dyn Texture>,
+    threshold: f64,
+}
+
+impl AlphaMask {
+    pub fn new(texture: Arc<dyn Texture>, threshold: f64) -> Self {
+        AlphaMask { texture, threshold }
+    }
+
+    pub fn is_opaque(&self, u: f64, v: f64, p: &Point3) -> bool {
+        color::luminance(self.texture.value(u, v, p)) >= self.threshold
+    }
 }
@@ -1,9 +1,17 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
 use crate::ray::Ray;
 
 #[derive(Default)]
 pub struct HittableList {
     objects: Vec<Box<dyn Hittable>>,
+    // Object IDs by name, for objects that can be referred to by the user
+    names: HashMap<String, usize>,
+    // Material IDs by address, counted in the order the materials are added
+    materials: HashMap<usize, usize>,
 }
 
 impl HittableList {
@@ -12,8 +20,32 @@ impl HittableList {
     }
 
     pub fn add(&mut self, object: Box<dyn Hittable>) {
+        for mat in object.materials() {
+            let next = self.materials.len();
+            self.materials.entry(address(mat)).or_insert(next);
+        }
         self.objects.push(object);
     }
+
+    pub fn add_named(&mut self, name: &str, object: Box<dyn Hittable>) {
+        self.names.insert(name.to_string(), self.objects.len());
+        self.add(object);
+    }
+
+    /// The ID that hits on the object called `name` report.
+    pub fn object_id(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    /// The ID of a material used by the objects in the list. It depends
+    /// only on the order the objects were added, not on the view.
+    pub fn material_id(&self, mat: &Arc<dyn Material>) -> Option<usize> {
+        self.materials.get(&address(mat)).copied()
+    }
+}
+
+fn address(mat: &Arc<dyn Material>) -> usize {
+    Arc::as_ptr(mat) as *const () as usize
 }
 
 impl Hittable for HittableList {
@@ -22,14 +54,19 @@ impl Hittable for HittableList {
         let mut hit_anything = false;
         let mut closest_so_far = t_max;
 
-        for object in &self.objects {
+        for (i, object) in self.objects.iter().enumerate() {
             if object.hit(ray, t_min, closest_so_far, &mut temp_rec) {
                 hit_anything = true;
                 closest_so_far = temp_rec.t;
+                temp_rec.object_id = i;
                 *rec = temp_rec.clone();
             }
         }
 
         hit_anything
     }
+
+    fn materials(&self) -> Vec<&Arc<dyn Material>> {
+        self.objects.iter().flat_map(|o| o.materials()).collect()
+    }
 }
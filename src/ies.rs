@@ -0,0 +1,320 @@
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+
+use crate::common;
+use crate::vec3::{self, Vec3};
+
+/// Angular intensity distribution read from an IES LM-63 photometric file.
+/// Only type C photometry, the common case for building luminaires, is
+/// supported: vertical angles are measured from the nadir and horizontal
+/// angles around it.
+pub struct IesProfile {
+    vertical_angles: Vec<f64>,
+    horizontal_angles: Vec<f64>,
+    // candela[h][v], already scaled by the candela multiplier
+    candela: Vec<Vec<f64>>,
+}
+
+impl IesProfile {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<IesProfile> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<IesProfile> {
+        let mut lines = text.lines();
+
+        // Skip the version line and keywords up to the TILT specification
+        let tilt = loop {
+            let line = lines
+                .next()
+                .ok_or_else(|| invalid_data("missing TILT line"))?;
+            if let Some(tilt) = line.trim().strip_prefix("TILT=") {
+                break tilt.trim().to_string();
+            }
+        };
+
+        let mut numbers = lines.flat_map(|line| line.split_whitespace()).map(|token| {
+            token
+                .parse::<f64>()
+                .map_err(|_| invalid_data("malformed number"))
+        });
+        let mut next = || {
+            numbers
+                .next()
+                .unwrap_or_else(|| Err(invalid_data("truncated IES data")))
+        };
+
+        if tilt == "INCLUDE" {
+            // Lamp-to-luminaire geometry, then angle and multiplier pairs. The
+            // tilt only matters for lamps mounted off vertical and is ignored.
+            next()?;
+            let pairs = next()? as usize;
+            for _ in 0..2 * pairs {
+                next()?;
+            }
+        } else if tilt != "NONE" {
+            return Err(invalid_data("external TILT files are not supported"));
+        }
+
+        let _lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let vertical_count = next()? as usize;
+        let horizontal_count = next()? as usize;
+        let photometric_type = next()? as i32;
+        let _units = next()?;
+        let _width = next()?;
+        let _length = next()?;
+        let _height = next()?;
+        let _ballast_factor = next()?;
+        let _future_use = next()?;
+        let _input_watts = next()?;
+
+        if photometric_type != 1 {
+            return Err(invalid_data("only type C photometry is supported"));
+        }
+        if vertical_count == 0 || horizontal_count == 0 {
+            return Err(invalid_data("empty candela table"));
+        }
+
+        let vertical_angles = (0..vertical_count)
+            .map(|_| next())
+            .collect::<io::Result<Vec<_>>>()?;
+        let horizontal_angles = (0..horizontal_count)
+            .map(|_| next())
+            .collect::<io::Result<Vec<_>>>()?;
+        let candela = (0..horizontal_count)
+            .map(|_| {
+                (0..vertical_count)
+                    .map(|_| next().map(|c| c * multiplier))
+                    .collect::<io::Result<Vec<_>>>()
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        Ok(IesProfile {
+            vertical_angles,
+            horizontal_angles,
+            candela,
+        })
+    }
+
+    /// Candela at the given angles in degrees, bilinearly interpolated and
+    /// unfolded according to the file's horizontal symmetry.
+    pub fn candela(&self, vertical: f64, horizontal: f64) -> f64 {
+        let horizontal = self.fold_horizontal(horizontal.rem_euclid(360.0));
+        let (h0, h1, th) = Self::bracket(&self.horizontal_angles, horizontal);
+        let (v0, v1, tv) = Self::bracket(&self.vertical_angles, vertical);
+
+        let lerp = |row: &Vec<f64>| (1.0 - tv) * row[v0] + tv * row[v1];
+        (1.0 - th) * lerp(&self.candela[h0]) + th * lerp(&self.candela[h1])
+    }
+
+    /// Candela towards `direction`, given the luminaire's nadir and the
+    /// direction of its zero horizontal angle.
+    pub fn candela_towards(&self, direction: Vec3, nadir: Vec3, reference: Vec3) -> f64 {
+        let d = vec3::unit_vector(direction);
+        let cos_v = common::clamp(vec3::dot(d, nadir), -1.0, 1.0);
+        let side = vec3::cross(nadir, reference);
+        let horizontal = f64::atan2(vec3::dot(d, side), vec3::dot(d, reference));
+
+        self.candela(f64::acos(cos_v).to_degrees(), horizontal.to_degrees())
+    }
+
+    fn fold_horizontal(&self, h: f64) -> f64 {
+        match self.horizontal_angles.last() {
+            // Rotationally symmetric
+            Some(last) if *last == 0.0 => 0.0,
+            // Symmetric in each quadrant
+            Some(last) if *last == 90.0 => {
+                let h = h % 180.0;
+                if h > 90.0 { 180.0 - h } else { h }
+            }
+            // Symmetric about the 0-180 degree plane
+            Some(last) if *last == 180.0 => {
+                if h > 180.0 {
+                    360.0 - h
+                } else {
+                    h
+                }
+            }
+            // Symmetric about the 90-270 degree plane, which mirrors h to
+            // 180 - h
+            Some(last) if *last == 270.0 && self.horizontal_angles[0] == 90.0 => {
+                if h < 90.0 {
+                    180.0 - h
+                } else if h > 270.0 {
+                    540.0 - h
+                } else {
+                    h
+                }
+            }
+            _ => h,
+        }
+    }
+
+    /// Indices of the samples around `x` and the interpolation weight
+    /// between them, clamping outside the table.
+    fn bracket(angles: &[f64], x: f64) -> (usize, usize, f64) {
+        let last = angles.len() - 1;
+        if x <= angles[0] {
+            return (0, 0, 0.0);
+        }
+        if x >= angles[last] {
+            return (last, last, 0.0);
+        }
+
+        let i = angles.partition_point(|a| *a <= x) - 1;
+        let t = (x - angles[i]) / (angles[i + 1] - angles[i]);
+        (i, i + 1, t)
+    }
+}
+
+fn invalid_data(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Downlight with a bilateral-symmetric table, adapted from the LM-63-2002
+    // example file.
+    const DOWNLIGHT: &str = "IESNA:LM-63-2002
+[TEST] simple downlight
+[MANUFAC] example
+TILT=NONE
+1 1000 2.0 5 3 1 1 0.5 0.5 0.1
+1.0 1.0 50
+0 22.5 45 67.5 90
+0 90 180
+500 450 300 100 0
+500 400 250 50 0
+500 350 200 0 0
+";
+
+    #[test]
+    fn parses_candela_table_with_multiplier() {
+        let profile = IesProfile::parse(DOWNLIGHT).unwrap();
+        assert_eq!(profile.vertical_angles, vec![0.0, 22.5, 45.0, 67.5, 90.0]);
+        assert_eq!(profile.horizontal_angles, vec![0.0, 90.0, 180.0]);
+        assert_eq!(profile.candela[1], vec![1000.0, 800.0, 500.0, 100.0, 0.0]);
+    }
+
+    #[test]
+    fn looks_up_and_interpolates() {
+        let profile = IesProfile::parse(DOWNLIGHT).unwrap();
+        assert_eq!(profile.candela(0.0, 0.0), 1000.0);
+        assert_eq!(profile.candela(45.0, 180.0), 400.0);
+        assert_eq!(profile.candela(33.75, 0.0), 750.0);
+        assert_eq!(profile.candela(45.0, 45.0), 550.0);
+        // Beyond the last vertical angle the table is clamped
+        assert_eq!(profile.candela(120.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn unfolds_bilateral_symmetry() {
+        let profile = IesProfile::parse(DOWNLIGHT).unwrap();
+        assert_eq!(profile.candela(22.5, 270.0), profile.candela(22.5, 90.0));
+        assert_eq!(profile.candela(22.5, -90.0), profile.candela(22.5, 90.0));
+    }
+
+    #[test]
+    fn unfolds_symmetry_about_the_90_270_plane() {
+        let text = "IESNA:LM-63-2002
+TILT=NONE
+1 1000 1.0 2 3 1 1 0 0 0
+1.0 1.0 10
+0 90
+90 180 270
+100 0
+200 0
+300 0
+";
+        let profile = IesProfile::parse(text).unwrap();
+        assert_eq!(profile.candela(0.0, 135.0), 150.0);
+        assert_eq!(profile.candela(0.0, 45.0), profile.candela(0.0, 135.0));
+        assert_eq!(profile.candela(0.0, 0.0), 200.0);
+        assert_eq!(profile.candela(0.0, 315.0), 250.0);
+        assert_eq!(profile.candela(0.0, 300.0), profile.candela(0.0, 240.0));
+    }
+
+    // The example file of the LM-63-2002 standard: a 400W metal halide flood
+    // with quadrant symmetry and tilt data
+    const STANDARD_EXAMPLE: &str = "IESNA:LM-63-2002
+[TEST] ABC1234 ABC Laboratories
+[ISSUEDATE] 28-FEB-2002
+[MANUFAC] Aardvark Lighting Inc.
+[LUMCAT] SKYVIEW 123-XYZ-abs-400
+[LUMINAIRE] Wide beam flood to be used without tilt
+[LAMPCAT] MH ABC1234 400W
+[LAMP] 400 Watt Metal Halide
+[OTHER] This luminaire is useful as an indirect flood
+[MORE] and to reduce light pollution in down light
+[MORE] applications.
+TILT=INCLUDE
+1
+13
+0 15 30 45 60 75 90 105 120 135 150 165 180
+1.0 .95 .94 .90 .88 .87 .98 .87 .88 .90 .94 .95 1.0
+1 50000 1 5 3 1 1 .5 .6 0
+1.0 1.0 495
+0 22.5 45 67.5 90
+0 45 90
+10000 50000 25000 10000 5000
+10000 35000 16000 8000 3000
+10000 20000 10000 5000 1000
+";
+
+    #[test]
+    fn reads_the_standard_example() {
+        let profile = IesProfile::parse(STANDARD_EXAMPLE).unwrap();
+        assert_eq!(profile.vertical_angles, vec![0.0, 22.5, 45.0, 67.5, 90.0]);
+        assert_eq!(profile.horizontal_angles, vec![0.0, 45.0, 90.0]);
+        assert_eq!(profile.candela(22.5, 0.0), 50000.0);
+        assert_eq!(profile.candela(45.0, 45.0), 16000.0);
+        assert_eq!(profile.candela(90.0, 90.0), 1000.0);
+        // Quadrant symmetry carries the table around the luminaire
+        assert_eq!(profile.candela(22.5, 135.0), 35000.0);
+        assert_eq!(profile.candela(67.5, 270.0), 5000.0);
+        assert_eq!(profile.candela(45.0, 337.5), profile.candela(45.0, 22.5));
+        assert_eq!(profile.candela(33.75, 0.0), 37500.0);
+    }
+
+    #[test]
+    fn skips_included_tilt_data() {
+        let text = "IESNA91
+TILT=INCLUDE
+1
+2
+0 90
+1.0 0.5
+1 -1 1.0 2 1 1 1 0 0 0
+1.0 1.0 10
+0 90
+0
+120 60
+";
+        let profile = IesProfile::parse(text).unwrap();
+        assert_eq!(profile.candela(0.0, 0.0), 120.0);
+        assert_eq!(profile.candela(45.0, 123.0), 90.0);
+    }
+
+    #[test]
+    fn orients_by_nadir_and_reference() {
+        let profile = IesProfile::parse(DOWNLIGHT).unwrap();
+        let nadir = Vec3::new(0.0, -1.0, 0.0);
+        let reference = Vec3::new(1.0, 0.0, 0.0);
+        let straight_down = profile.candela_towards(nadir, nadir, reference);
+        let sideways = profile.candela_towards(Vec3::new(1.0, -1.0, 0.0), nadir, reference);
+        assert_eq!(straight_down, 1000.0);
+        assert!((sideways - 600.0).abs() < 1.0e-9);
+    }
+
+    #[test]
+    fn rejects_unsupported_files() {
+        assert!(IesProfile::parse("TILT=lamp.tlt\n").is_err());
+        assert!(IesProfile::parse("no tilt here").is_err());
+        assert!(IesProfile::parse("TILT=NONE\n1 1000 1").is_err());
+    }
+}
@@ -0,0 +1,324 @@
+use std::fs;
+use std::io::{self, Cursor, Error, ErrorKind};
+use std::path::Path;
+
+use crate::color::Color;
+use crate::colorspace;
+use crate::display;
+use exr::prelude::{ReadChannels, ReadLayers};
+
+#[derive(Clone, Default)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Image {
+            width,
+            height,
+            pixels: vec![Color::default(); width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    /// Reads a binary (P6) or ASCII (P3) PPM. Channel values are scaled to
+    /// [0, 1] but otherwise left exactly as stored, so data maps such as normal
+    /// maps come through untouched.
+    pub fn read_ppm(path: impl AsRef<Path>) -> io::Result<Image> {
+        Self::parse_ppm(&fs::read(path)?)
+    }
+
+    /// Reads a PPM, PNG, color (PF) or grayscale (Pf) PFM, or OpenEXR image,
+    /// telling them apart by their contents, as linear colors: PPMs and PNGs
+    /// are taken to hold display colors, PFMs and EXRs linear ones.
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Image> {
+        let bytes = fs::read(path)?;
+        match bytes.get(..4).unwrap_or_default() {
+            [b'P', b'3' | b'6', ..] => Ok(Self::parse_ppm(&bytes)?.into_linear()),
+            [b'P', b'F' | b'f', ..] => Self::parse_pfm(&bytes),
+            [0x89, b'P', b'N', b'G'] => Ok(Self::parse_png(&bytes)?.into_linear()),
+            [0x76, 0x2f, 0x31, 0x01] => Self::parse_exr(&bytes),
+            _ => Err(invalid_data("not a PPM, PFM, PNG or EXR file")),
+        }
+    }
+
+    fn parse_ppm(bytes: &[u8]) -> io::Result<Image> {
+        let mut pos = 0;
+
+        let magic = next_token(bytes, &mut pos)?;
+        let binary = match magic.as_str() {
+            "P6" => true,
+            "P3" => false,
+            _ => return Err(invalid_data("not a P3 or P6 PPM file")),
+        };
+
+        let width = parse_token(bytes, &mut pos)?;
+        let height = parse_token(bytes, &mut pos)?;
+        let max_value = parse_token(bytes, &mut pos)?;
+        if max_value == 0 || max_value > 255 {
+            return Err(invalid_data("unsupported PPM max value"));
+        }
+        let scale = 1.0 / max_value as f64;
+
+        let count = width
+            .checked_mul(height)
+            .and_then(|n| n.checked_mul(3))
+            .ok_or_else(|| invalid_data("PPM image too large"))?;
+        let mut samples = Vec::with_capacity(count.min(bytes.len()));
+        if binary {
+            // Exactly one whitespace byte separates the header from the raster.
+            let raster =
+                raster(bytes, pos, count).ok_or_else(|| invalid_data("truncated PPM raster"))?;
+            samples.extend(raster.iter().map(|b| *b as f64 * scale));
+        } else {
+            for _ in 0..count {
+                samples.push(parse_token(bytes, &mut pos)? as f64 * scale);
+            }
+        }
+
+        let pixels = samples
+            .chunks_exact(3)
+            .map(|c| Color::new(c[0], c[1], c[2]))
+            .collect();
+
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    fn parse_pfm(bytes: &[u8]) -> io::Result<Image> {
+        let mut pos = 0;
+        let channels = match next_token(bytes, &mut pos)?.as_str() {
+            "PF" => 3,
+            "Pf" => 1,
+            _ => return Err(invalid_data("not a PF or Pf PFM file")),
+        };
+        let width = parse_token(bytes, &mut pos)?;
+        let height = parse_token(bytes, &mut pos)?;
+        let scale: f64 = next_token(bytes, &mut pos)?
+            .parse()
+            .map_err(|_| invalid_data("malformed PFM header"))?;
+
+        let raster = width
+            .checked_mul(height)
+            .and_then(|n| n.checked_mul(channels * 4))
+            .and_then(|size| raster(bytes, pos, size))
+            .ok_or_else(|| invalid_data("truncated PFM raster"))?;
+        let samples: Vec<f64> = raster
+            .chunks_exact(4)
+            .map(|b| {
+                let b = [b[0], b[1], b[2], b[3]];
+                // A negative scale marks little-endian floats
+                let v = if scale < 0.0 {
+                    f32::from_le_bytes(b)
+                } else {
+                    f32::from_be_bytes(b)
+                };
+                v as f64
+            })
+            .collect();
+
+        // PFM stores the bottom row first
+        let mut image = Image::new(width, height);
+        for (i, c) in samples.chunks_exact(channels).enumerate() {
+            let (x, y) = (i % width, height - 1 - i / width);
+            let color = match c {
+                [r, g, b] => Color::new(*r, *g, *b),
+                _ => Color::new(c[0], c[0], c[0]),
+            };
+            image.set_pixel(x, y, color);
+        }
+        Ok(image)
+    }
+
+    /// Decodes 8- and 16-bit gray and RGB PNGs; alpha is ignored.
+    fn parse_png(bytes: &[u8]) -> io::Result<Image> {
+        let mut decoder = png::Decoder::new(Cursor::new(bytes));
+        // Palettes, bit depths below 8 and transparency keys come out as
+        // 8-bit gray or RGB
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info()?;
+        let size = reader
+            .output_buffer_size()
+            .ok_or_else(|| invalid_data("PNG image too large"))?;
+        let mut buffer = vec![0; size];
+        let info = reader.next_frame(&mut buffer)?;
+        let buffer = &buffer[..info.buffer_size()];
+
+        let samples: Vec<f64> = match info.bit_depth {
+            png::BitDepth::Sixteen => buffer
+                .chunks_exact(2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]) as f64 / 65535.0)
+                .collect(),
+            _ => buffer.iter().map(|b| *b as f64 / 255.0).collect(),
+        };
+        let (width, height) = (info.width as usize, info.height as usize);
+        let channels = info.color_type.samples();
+        let mut image = Image::new(width, height);
+        for (i, c) in samples.chunks_exact(channels).enumerate() {
+            let color = match c {
+                [r, g, b, ..] => Color::new(*r, *g, *b),
+                _ => Color::new(c[0], c[0], c[0]),
+            };
+            image.set_pixel(i % width, i / width, color);
+        }
+        Ok(image)
+    }
+
+    /// Decodes the first layer of an OpenEXR file that has RGB channels.
+    fn parse_exr(bytes: &[u8]) -> io::Result<Image> {
+        let exr = exr::prelude::read()
+            .no_deep_data()
+            .largest_resolution_level()
+            .rgba_channels(
+                |size, _| Image::new(size.width(), size.height()),
+                |image: &mut Image, position, (r, g, b, _): (f32, f32, f32, f32)| {
+                    let color = Color::new(r as f64, g as f64, b as f64);
+                    image.set_pixel(position.x(), position.y(), color);
+                },
+            )
+            .first_valid_layer()
+            .all_attributes()
+            .from_buffered(Cursor::new(bytes))
+            .map_err(|e| invalid_data(&e.to_string()))?;
+        Ok(exr.layer_data.channel_data.pixels)
+    }
+
+    /// Undoes the sRGB encoding applied by `color::write` and converts to
+    /// the working space, for images that hold display colors rather than
+    /// data.
+    pub fn into_linear(mut self) -> Self {
+        for pixel in &mut self.pixels {
+            *pixel = colorspace::input(Color::new(
+                display::srgb_decode(pixel.x()),
+                display::srgb_decode(pixel.y()),
+                display::srgb_decode(pixel.z()),
+            ));
+        }
+        self
+    }
+}
+
+fn next_token(bytes: &[u8], pos: &mut usize) -> io::Result<String> {
+    loop {
+        while *pos < bytes.len() && bytes[*pos].is_ascii_whitespace() {
+            *pos += 1;
+        }
+        if *pos < bytes.len() && bytes[*pos] == b'#' {
+            while *pos < bytes.len() && bytes[*pos] != b'\n' {
+                *pos += 1;
+            }
+            continue;
+        }
+        break;
+    }
+
+    let start = *pos;
+    while *pos < bytes.len() && !bytes[*pos].is_ascii_whitespace() {
+        *pos += 1;
+    }
+    if start == *pos {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            "unexpected end of PPM",
+        ));
+    }
+    Ok(String::from_utf8_lossy(&bytes[start..*pos]).into_owned())
+}
+
+/// The `size` bytes of raster data that follow the header ending at `pos`,
+/// after the single whitespace byte that separates them.
+fn raster(bytes: &[u8], pos: usize, size: usize) -> Option<&[u8]> {
+    if !bytes.get(pos).is_some_and(u8::is_ascii_whitespace) {
+        return None;
+    }
+    bytes.get(pos + 1..(pos + 1).checked_add(size)?)
+}
+
+fn parse_token(bytes: &[u8], pos: &mut usize) -> io::Result<usize> {
+    next_token(bytes, pos)?
+        .parse()
+        .map_err(|_| invalid_data("malformed PPM header"))
+}
+
+fn invalid_data(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_png() {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, 2, 1);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Sixteen);
+        let mut writer = encoder.write_header().unwrap();
+        writer
+            .write_image_data(&[255, 255, 0, 0, 128, 0, 0, 0, 0, 0, 0, 255])
+            .unwrap();
+        writer.finish().unwrap();
+
+        let image = Image::parse_png(&bytes).unwrap();
+        assert_eq!((image.width(), image.height()), (2, 1));
+        let c = image.pixel(0, 0);
+        assert!(c.x() == 1.0 && c.y() == 0.0 && (c.z() - 32768.0 / 65535.0).abs() < 1.0e-12);
+        assert!((image.pixel(1, 0).z() - 255.0 / 65535.0).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn reads_exr() {
+        use exr::prelude::{SpecificChannels, WritableImage};
+
+        let pixels = SpecificChannels::rgb(|p: exr::math::Vec2<usize>| (p.x() as f32, 0.5, 2.0));
+        let mut bytes = Vec::new();
+        exr::prelude::Image::from_channels((3, 2), pixels)
+            .write()
+            .to_buffered(Cursor::new(&mut bytes))
+            .unwrap();
+
+        let image = Image::parse_exr(&bytes).unwrap();
+        assert_eq!((image.width(), image.height()), (3, 2));
+        let c = image.pixel(2, 1);
+        assert!(c.x() == 2.0 && c.y() == 0.5 && c.z() == 2.0);
+    }
+
+    #[test]
+    fn rejects_oversized_and_malformed_pfm_headers() {
+        let huge = b"PF\n4294967296 4294967296\n-1.0\n";
+        let err = Image::parse_pfm(huge).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let huge = b"P6\n4294967296 4294967296\n255\n";
+        assert_eq!(
+            Image::parse_ppm(huge).err().unwrap().kind(),
+            ErrorKind::InvalidData
+        );
+
+        let mut pfm = b"PF\n1 1\n-1.0\n".to_vec();
+        pfm.extend([0.25f32, 0.5, 1.0].iter().flat_map(|v| v.to_le_bytes()));
+        assert_eq!(Image::parse_pfm(&pfm).unwrap().pixel(0, 0).y(), 0.5);
+        assert!(Image::parse_pfm(&pfm[..pfm.len() - 1]).is_err());
+    }
+}
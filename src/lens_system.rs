@@ -0,0 +1,490 @@
+use crate::aperture::Aperture;
+use crate::sampler;
+use crate::vec3::{self, Point3, Vec3};
+
+/// Double-Gauss f/2 prescription (US patent 2,673,491, from Smith's "Modern
+/// Lens Design"), scaled to a 50mm focal length.
+pub const DOUBLE_GAUSS: &str = "\
+# radius  thickness  ior    aperture (mm, front to rear)
+29.475    3.76       1.67   25.2
+84.83     0.12       1      25.2
+19.275    4.025      1.67   23
+40.77     3.275      1.699  23
+12.75     5.705      1      18
+0         4.5        0      17.1
+-14.495   1.18       1.603  17
+40.77     6.065      1.658  20
+-20.385   0.19       1      20
+437.065   2.165      1.717  20
+-39.73    0          1      20
+";
+
+// Film radii at which the exit pupil is bounded, and the resolution of the
+// grid of rays each bound is measured with
+const PUPIL_BINS: usize = 64;
+const PUPIL_GRID: usize = 64;
+
+/// One refracting surface of a lens, in meters.
+#[derive(Clone)]
+struct Surface {
+    // Radius of curvature, positive when the center is towards the film;
+    // zero for the aperture stop
+    radius: f64,
+    // Distance to the next surface, or to the film for the last one
+    thickness: f64,
+    // Index of refraction behind the surface; zero or one for air
+    ior: f64,
+    aperture_radius: f64,
+}
+
+impl Surface {
+    fn is_stop(&self) -> bool {
+        self.radius == 0.0
+    }
+}
+
+/// A camera lens made of spherical elements, traced ray by ray. Rays start
+/// on the film and are refracted by every surface in turn, so the image
+/// shows the lens's own distortion, vignetting and bokeh. Near the edge of
+/// the frame the front and rear elements cut into the aperture and blurred
+/// highlights take on a cat's-eye shape.
+///
+/// Lens space has the film at z = 0 and looks down -z with +y up, like
+/// camera space in `Projection::camera_ray`.
+#[derive(Clone)]
+pub struct LensSystem {
+    surfaces: Vec<Surface>,
+    stop: Aperture,
+    film_width: f64,
+    film_height: f64,
+    // Effective focal length in meters and f-number at the current stop
+    pub focal_length: f64,
+    pub f_number: f64,
+    // Bounds on the rear element's plane of the rays that pass the lens,
+    // for film points on the +x axis out to the corner of the film
+    pupil_bounds: Vec<Option<[f64; 4]>>,
+    // Area of the rear element through which light reaches the film center
+    center_area: f64,
+}
+
+impl LensSystem {
+    /// Builds the lens in `prescription`, one surface per line from the
+    /// front element to the rear: radius of curvature, thickness, index of
+    /// refraction and aperture diameter, all lengths in millimeters. A
+    /// radius of 0 marks the aperture stop, which takes the shape of
+    /// `stop`. The film is `film_width` by `film_height` meters. The lens
+    /// is moved to focus at `focus_distance` meters from the film and, with
+    /// `f_number` set, the stop is closed down to it.
+    pub fn new(
+        prescription: &str,
+        film_width: f64,
+        film_height: f64,
+        focus_distance: f64,
+        f_number: Option<f64>,
+        stop: Aperture,
+    ) -> Result<LensSystem, String> {
+        let mut lens = LensSystem {
+            surfaces: parse(prescription)?,
+            stop,
+            film_width,
+            film_height,
+            focal_length: 0.0,
+            f_number: 0.0,
+            pupil_bounds: Vec::new(),
+            center_area: 0.0,
+        };
+
+        lens.focus(focus_distance)?;
+
+        let pupil = lens.entrance_pupil_radius();
+        if pupil <= 0.0 {
+            return Err("no light passes the lens".to_string());
+        }
+        lens.f_number = lens.focal_length / (2.0 * pupil);
+        if let Some(f_number) = f_number.filter(|n| *n > lens.f_number) {
+            // The entrance pupil is the stop as seen through the front
+            // elements, so it shrinks in proportion
+            let scale = lens.f_number / f_number;
+            if let Some(stop) = lens.surfaces.iter_mut().find(|s| s.is_stop()) {
+                stop.aperture_radius *= scale;
+                lens.f_number = lens.focal_length / (2.0 * lens.entrance_pupil_radius());
+            }
+        }
+
+        lens.center_area = lens.exit_pupil(0.0).1;
+        if lens.center_area <= 0.0 {
+            return Err("no light reaches the film center".to_string());
+        }
+
+        // A shaped stop is not symmetric about the axis, so the bounds are
+        // taken with the circle around it and rotated to each film point
+        let mut round = lens.clone();
+        round.stop = Aperture::Circle;
+        if let Some(stop) = round.surfaces.iter_mut().find(|s| s.is_stop()) {
+            stop.aperture_radius *= lens.stop.bounding_radius();
+        }
+        let film_radius = film_width.hypot(film_height) / 2.0;
+        lens.pupil_bounds = (0..PUPIL_BINS)
+            .map(|i| {
+                let r0 = film_radius * i as f64 / PUPIL_BINS as f64;
+                let r1 = film_radius * (i + 1) as f64 / PUPIL_BINS as f64;
+                union(round.exit_pupil(r0).0, round.exit_pupil(r1).0)
+            })
+            .collect();
+        Ok(lens)
+    }
+
+    /// Lens-space ray for the film position (x, y), with both coordinates
+    /// in [-1, 1] from the bottom left to the top right of the image, and
+    /// the weight of its radiance. Returns None for rays that the lens
+    /// barrel or the stop block.
+    pub fn camera_ray(&self, x: f64, y: f64) -> Option<(Point3, Vec3, f64)> {
+        // The lens turns the image upside down
+        let (fx, fy) = (-x * self.film_width / 2.0, -y * self.film_height / 2.0);
+        let r = fx.hypot(fy);
+        let film_radius = self.film_width.hypot(self.film_height) / 2.0;
+        let bin = ((r / film_radius * PUPIL_BINS as f64) as usize).min(PUPIL_BINS - 1);
+        let [x0, y0, x1, y1] = self.pupil_bounds[bin]?;
+
+        // Aim at the exit pupil, rotated from the +x axis to the film point
+        let (u1, u2) = sampler::get_2d();
+        let (px, py) = (x0 + u1 * (x1 - x0), y0 + u2 * (y1 - y0));
+        let (sin, cos) = if r > 0.0 {
+            (fy / r, fx / r)
+        } else {
+            (0.0, 1.0)
+        };
+        let film = Point3::new(fx, fy, 0.0);
+        let rear = Point3::new(
+            cos * px - sin * py,
+            sin * px + cos * py,
+            -self.film_distance(),
+        );
+        let direction = vec3::unit_vector(rear - film);
+
+        let (origin, out) = self.trace_from_film(film, direction)?;
+        // Irradiance falls off with the fourth power of the cosine to the
+        // axis; scaled so that the film center sees the scene unchanged
+        let cos2 = direction.z() * direction.z();
+        let area = (x1 - x0) * (y1 - y0);
+        Some((origin, out, cos2 * cos2 * area / self.center_area))
+    }
+
+    /// Summary of the focused lens for the log.
+    pub fn describe(&self) -> String {
+        format!(
+            "{} surfaces, {:.1}mm f/{:.1}, film at {:.2}mm",
+            self.surfaces.len(),
+            self.focal_length * 1000.0,
+            self.f_number,
+            self.film_distance() * 1000.0
+        )
+    }
+
+    fn film_distance(&self) -> f64 {
+        self.surfaces.last().map_or(0.0, |s| s.thickness)
+    }
+
+    fn front_z(&self) -> f64 {
+        self.surfaces.iter().map(|s| s.thickness).sum()
+    }
+
+    /// Moves the lens away from the film until objects at `distance` are
+    /// sharp, using the thick-lens approximation of the whole system.
+    fn focus(&mut self, distance: f64) -> Result<(), String> {
+        let (principal_front, principal_rear, focal_length) = self.cardinal_points()?;
+        self.focal_length = focal_length;
+
+        // Moving the lens by delta must satisfy 1/(a - delta) + 1/(b + delta)
+        // = 1/f, with a the object distance and b the image distance
+        let a = distance + principal_front;
+        let b = -principal_rear;
+        let discriminant = (a + b) * (a + b) - 4.0 * (a + b) * focal_length;
+        if discriminant < 0.0 {
+            return Err(format!("the lens cannot focus as close as {distance}m"));
+        }
+        let delta = 0.5 * ((a - b) - discriminant.sqrt());
+
+        let last = self.surfaces.last_mut().unwrap();
+        last.thickness += delta;
+        if last.thickness <= 0.0 {
+            return Err(format!("the lens cannot focus at {distance}m"));
+        }
+        Ok(())
+    }
+
+    /// Positions of the front and rear principal planes and the focal
+    /// length, found by tracing rays parallel to the axis from both sides.
+    fn cardinal_points(&self) -> Result<(f64, f64, f64), String> {
+        let height = 0.01 * self.film_height.hypot(self.film_width);
+        let error = || "the lens does not form an image".to_string();
+
+        let scene = Point3::new(height, 0.0, -self.front_z() - 1.0);
+        let (o, d) = self
+            .trace_from_scene(scene, Vec3::new(0.0, 0.0, 1.0))
+            .ok_or_else(error)?;
+        let principal_rear = o.z() + (height - o.x()) / d.x() * d.z();
+        let focus_rear = o.z() - o.x() / d.x() * d.z();
+
+        let film = Point3::new(height, 0.0, 1.0);
+        let (o, d) = self
+            .trace_from_film(film, Vec3::new(0.0, 0.0, -1.0))
+            .ok_or_else(error)?;
+        let principal_front = o.z() + (height - o.x()) / d.x() * d.z();
+
+        let focal_length = focus_rear - principal_rear;
+        if focal_length.is_nan() || focal_length <= 0.0 {
+            return Err(error());
+        }
+        Ok((principal_front, principal_rear, focal_length))
+    }
+
+    /// Radius of the widest beam parallel to the axis that passes the lens.
+    fn entrance_pupil_radius(&self) -> f64 {
+        let front = -self.front_z() - 1.0;
+        let passes = |h: f64| {
+            self.trace_from_scene(Point3::new(h, 0.0, front), Vec3::new(0.0, 0.0, 1.0))
+                .is_some()
+        };
+
+        let (mut inside, mut outside) = (0.0, self.surfaces[0].aperture_radius);
+        for _ in 0..40 {
+            let middle = 0.5 * (inside + outside);
+            if passes(middle) {
+                inside = middle;
+            } else {
+                outside = middle;
+            }
+        }
+        inside
+    }
+
+    /// Bounds on the rear element's plane of the rays from the film point
+    /// `radius` along +x that pass the lens, and the area they pass through
+    /// weighted by the cosine falloff, measured on a grid over the rear
+    /// element.
+    fn exit_pupil(&self, radius: f64) -> (Option<[f64; 4]>, f64) {
+        let rear = self.surfaces.last().unwrap().aperture_radius;
+        let cell = 2.0 * rear / PUPIL_GRID as f64;
+        let film = Point3::new(radius, 0.0, 0.0);
+        let mut bounds = None;
+        let mut area = 0.0;
+
+        for j in 0..PUPIL_GRID {
+            for i in 0..PUPIL_GRID {
+                let x = -rear + (i as f64 + 0.5) * cell;
+                let y = -rear + (j as f64 + 0.5) * cell;
+                let target = Point3::new(x, y, -self.film_distance());
+                let direction = vec3::unit_vector(target - film);
+                if self.trace_from_film(film, direction).is_none() {
+                    continue;
+                }
+                area += cell * cell * direction.z().powi(4);
+                // Grow by a cell so that the bounds cover the grid's gaps
+                let cell_bounds = [x - cell, y - cell, x + cell, y + cell];
+                bounds = union(bounds, Some(cell_bounds));
+            }
+        }
+        (bounds, area)
+    }
+
+    fn trace_from_film(&self, mut origin: Point3, mut direction: Vec3) -> Option<(Point3, Vec3)> {
+        let mut z = 0.0;
+        for (i, surface) in self.surfaces.iter().enumerate().rev() {
+            z -= surface.thickness;
+            let ior_before = air_or(surface.ior);
+            let ior_after = if i > 0 {
+                air_or(self.surfaces[i - 1].ior)
+            } else {
+                1.0
+            };
+            (origin, direction) =
+                self.cross(surface, z, origin, direction, ior_before / ior_after)?;
+        }
+        Some((origin, direction))
+    }
+
+    fn trace_from_scene(&self, mut origin: Point3, mut direction: Vec3) -> Option<(Point3, Vec3)> {
+        let mut z = -self.front_z();
+        for (i, surface) in self.surfaces.iter().enumerate() {
+            let ior_before = if i > 0 {
+                air_or(self.surfaces[i - 1].ior)
+            } else {
+                1.0
+            };
+            let ior_after = air_or(surface.ior);
+            (origin, direction) =
+                self.cross(surface, z, origin, direction, ior_before / ior_after)?;
+            z += surface.thickness;
+        }
+        Some((origin, direction))
+    }
+
+    /// Carries a ray through the surface whose vertex is at `z`, refracting
+    /// it with the ratio `eta` of the indices on either side.
+    fn cross(
+        &self,
+        surface: &Surface,
+        z: f64,
+        origin: Point3,
+        direction: Vec3,
+        eta: f64,
+    ) -> Option<(Point3, Vec3)> {
+        if surface.is_stop() {
+            let t = (z - origin.z()) / direction.z();
+            let p = origin + t * direction;
+            let r = surface.aperture_radius;
+            if t < 0.0 || !self.stop.contains(p.x() / r, p.y() / r) {
+                return None;
+            }
+            return Some((p, direction));
+        }
+
+        let center = Point3::new(0.0, 0.0, z + surface.radius);
+        let (t, normal) = intersect_sphere(surface.radius, center, origin, direction)?;
+        let p = origin + t * direction;
+        if p.x() * p.x() + p.y() * p.y() > surface.aperture_radius * surface.aperture_radius {
+            return None;
+        }
+        let refracted = refract(-vec3::unit_vector(direction), normal, eta)?;
+        Some((p, refracted))
+    }
+}
+
+fn parse(prescription: &str) -> Result<Vec<Surface>, String> {
+    let mut surfaces = Vec::new();
+    for line in prescription.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let values = line
+            .split_whitespace()
+            .map(|v| v.parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .map_err(|_| format!("invalid lens surface '{line}'"))?;
+        let [radius, thickness, ior, diameter] = values[..] else {
+            return Err(format!("lens surface '{line}' needs 4 values"));
+        };
+        if !(diameter > 0.0 && thickness >= 0.0 && ior >= 0.0) {
+            return Err(format!("invalid lens surface '{line}'"));
+        }
+        surfaces.push(Surface {
+            radius: radius / 1000.0,
+            thickness: thickness / 1000.0,
+            ior,
+            aperture_radius: diameter / 2000.0,
+        });
+    }
+
+    if surfaces.is_empty() {
+        return Err("the lens has no surfaces".to_string());
+    }
+    Ok(surfaces)
+}
+
+fn union(a: Option<[f64; 4]>, b: Option<[f64; 4]>) -> Option<[f64; 4]> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some([
+            a[0].min(b[0]),
+            a[1].min(b[1]),
+            a[2].max(b[2]),
+            a[3].max(b[3]),
+        ]),
+        (a, b) => a.or(b),
+    }
+}
+
+fn air_or(ior: f64) -> f64 {
+    if ior == 0.0 { 1.0 } else { ior }
+}
+
+/// Nearest intersection of the ray with the part of the sphere that faces
+/// the lens vertex, and the normal there facing the ray's origin.
+fn intersect_sphere(
+    radius: f64,
+    center: Point3,
+    origin: Point3,
+    direction: Vec3,
+) -> Option<(f64, Vec3)> {
+    let oc = origin - center;
+    let a = direction.length_squared();
+    let half_b = vec3::dot(oc, direction);
+    let c = oc.length_squared() - radius * radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let root = discriminant.sqrt();
+    let (t0, t1) = ((-half_b - root) / a, (-half_b + root) / a);
+    // The vertex lies on the near side of the sphere for rays heading
+    // towards its center, and on the far side otherwise
+    let closer = (direction.z() > 0.0) != (radius < 0.0);
+    let t = if closer { t0 } else { t1 };
+    if t < 0.0 {
+        return None;
+    }
+
+    let normal = vec3::unit_vector(oc + t * direction);
+    let normal = if vec3::dot(normal, direction) > 0.0 {
+        -normal
+    } else {
+        normal
+    };
+    Some((t, normal))
+}
+
+/// Direction of the ray refracted at a surface with `normal`, for `incoming`
+/// pointing back along the incident ray. None on total internal reflection.
+fn refract(incoming: Vec3, normal: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = vec3::dot(normal, incoming);
+    let sin2_t = eta * eta * f64::max(0.0, 1.0 - cos_i * cos_i);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = f64::sqrt(1.0 - sin2_t);
+    Some(eta * -incoming + (eta * cos_i - cos_t) * normal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn double_gauss_matches_its_design() {
+        let lens =
+            LensSystem::new(DOUBLE_GAUSS, 0.036, 0.024, 1000.0, None, Aperture::Circle).unwrap();
+        assert!((lens.focal_length - 0.05).abs() < 0.001);
+        assert!((lens.f_number - 2.0).abs() < 0.1);
+
+        let stopped = LensSystem::new(
+            DOUBLE_GAUSS,
+            0.036,
+            0.024,
+            1000.0,
+            Some(8.0),
+            Aperture::Circle,
+        )
+        .unwrap();
+        assert!((stopped.f_number - 8.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn rays_from_the_center_meet_at_the_focus_distance() {
+        let lens =
+            LensSystem::new(DOUBLE_GAUSS, 0.036, 0.024, 2.0, Some(8.0), Aperture::Circle).unwrap();
+        let mut crossings = Vec::new();
+        for _ in 0..1000 {
+            if let Some((origin, direction, _)) = lens.camera_ray(0.0, 0.0)
+                && direction.x().abs() > 1.0e-4
+            {
+                crossings.push(origin.z() - origin.x() / direction.x() * direction.z());
+            }
+        }
+        crossings.sort_by(f64::total_cmp);
+        let median = crossings[crossings.len() / 2];
+        assert!((median + 2.0).abs() < 0.05, "rays meet at {median}");
+    }
+}
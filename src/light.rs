@@ -0,0 +1,175 @@
+use std::sync::Arc;
+
+use crate::color::Color;
+use crate::colorspace;
+use crate::common;
+use crate::ies::IesProfile;
+use crate::vec3::{self, Point3, Vec3};
+
+/// Incident light arriving at a shading point from a delta light.
+pub struct LightSample {
+    // Unit direction from the shading point towards the light
+    pub wi: Vec3,
+    // Distance to the light, infinite for directional lights
+    pub distance: f64,
+    // Radiance arriving along `wi`, with distance falloff already applied
+    pub radiance: Color,
+}
+
+/// An analytic light that can only be reached by next-event estimation:
+/// scattered rays never hit it.
+pub trait Light: Send + Sync {
+    fn sample_li(&self, p: &Point3) -> Option<LightSample>;
+}
+
+pub struct PointLight {
+    position: Point3,
+    intensity: Color,
+    profile: Option<Arc<IesProfile>>,
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Color) -> Self {
+        PointLight {
+            position,
+            intensity: colorspace::input(intensity),
+            profile: None,
+        }
+    }
+
+    /// Scales `intensity` by the profile's candela values. The luminaire
+    /// hangs pointing down -Y, with its zero horizontal angle along +X.
+    pub fn with_profile(mut self, profile: Arc<IesProfile>) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+}
+
+impl Light for PointLight {
+    fn sample_li(&self, p: &Point3) -> Option<LightSample> {
+        let to_light = self.position - *p;
+        let distance_squared = to_light.length_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+
+        let wi = to_light / distance_squared.sqrt();
+        let candela = self.profile.as_ref().map_or(1.0, |profile| {
+            profile.candela_towards(-wi, Vec3::new(0.0, -1.0, 0.0), Vec3::new(1.0, 0.0, 0.0))
+        });
+
+        Some(LightSample {
+            wi,
+            distance: distance_squared.sqrt(),
+            radiance: candela * self.intensity / distance_squared,
+        })
+    }
+}
+
+pub struct SpotLight {
+    position: Point3,
+    direction: Vec3,
+    intensity: Color,
+    cos_total_width: f64,
+    cos_falloff_start: f64,
+    profile: Option<Arc<IesProfile>>,
+}
+
+impl SpotLight {
+    /// `total_width` is the cone half-angle in degrees beyond which no light
+    /// is emitted; intensity starts to fall off smoothly at `falloff_start`.
+    pub fn new(
+        position: Point3,
+        look_at: Point3,
+        intensity: Color,
+        total_width: f64,
+        falloff_start: f64,
+    ) -> Self {
+        SpotLight {
+            position,
+            direction: vec3::unit_vector(look_at - position),
+            intensity: colorspace::input(intensity),
+            cos_total_width: f64::cos(common::degress_to_radians(total_width)),
+            cos_falloff_start: f64::cos(common::degress_to_radians(falloff_start)),
+            profile: None,
+        }
+    }
+
+    /// Scales `intensity` by the profile's candela values, with the
+    /// profile's nadir along the spot direction.
+    pub fn with_profile(mut self, profile: Arc<IesProfile>) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        if cos_theta < self.cos_total_width {
+            return 0.0;
+        }
+        if cos_theta >= self.cos_falloff_start {
+            return 1.0;
+        }
+
+        // Smoothstep between the two cone edges
+        let t =
+            (cos_theta - self.cos_total_width) / (self.cos_falloff_start - self.cos_total_width);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample_li(&self, p: &Point3) -> Option<LightSample> {
+        let to_light = self.position - *p;
+        let distance_squared = to_light.length_squared();
+        if distance_squared == 0.0 {
+            return None;
+        }
+
+        let wi = to_light / distance_squared.sqrt();
+        let falloff = self.falloff(vec3::dot(-wi, self.direction));
+        if falloff <= 0.0 {
+            return None;
+        }
+
+        let candela = self.profile.as_ref().map_or(1.0, |profile| {
+            let a = if self.direction.x().abs() > 0.9 {
+                Vec3::new(0.0, 1.0, 0.0)
+            } else {
+                Vec3::new(1.0, 0.0, 0.0)
+            };
+            let reference = vec3::unit_vector(vec3::cross(self.direction, a));
+            profile.candela_towards(-wi, self.direction, reference)
+        });
+
+        Some(LightSample {
+            wi,
+            distance: distance_squared.sqrt(),
+            radiance: candela * falloff * self.intensity / distance_squared,
+        })
+    }
+}
+
+pub struct DirectionalLight {
+    // Direction the light travels in
+    direction: Vec3,
+    radiance: Color,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, radiance: Color) -> Self {
+        DirectionalLight {
+            direction: vec3::unit_vector(direction),
+            radiance: colorspace::input(radiance),
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample_li(&self, _p: &Point3) -> Option<LightSample> {
+        Some(LightSample {
+            wi: -self.direction,
+            distance: f64::INFINITY,
+            radiance: self.radiance,
+        })
+    }
+}
@@ -0,0 +1,161 @@
+use std::fs;
+use std::io::{self, Error, ErrorKind};
+use std::path::Path;
+
+use crate::color::Color;
+
+/// 3D color lookup table read from an Adobe/Resolve `.cube` file. Colors
+/// are looked up with trilinear interpolation; inputs outside the table's
+/// domain are clamped to it.
+pub struct Lut {
+    size: usize,
+    domain_min: Color,
+    domain_max: Color,
+    // Red varies fastest, then green, then blue
+    table: Vec<Color>,
+}
+
+impl Lut {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Lut> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> io::Result<Lut> {
+        let mut size = None;
+        let mut domain_min = Color::new(0.0, 0.0, 0.0);
+        let mut domain_max = Color::new(1.0, 1.0, 1.0);
+        let mut table = Vec::new();
+
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut tokens = line.split_whitespace();
+            let keyword = tokens.next().unwrap_or_default();
+            match keyword {
+                "TITLE" => {}
+                "LUT_3D_SIZE" => {
+                    let n = tokens
+                        .next()
+                        .and_then(|n| n.parse::<usize>().ok())
+                        .filter(|n| *n >= 2)
+                        .ok_or_else(|| invalid_data("malformed LUT_3D_SIZE"))?;
+                    size = Some(n);
+                }
+                "LUT_1D_SIZE" => return Err(invalid_data("1D LUTs are not supported")),
+                "DOMAIN_MIN" => domain_min = parse_color(tokens)?,
+                "DOMAIN_MAX" => domain_max = parse_color(tokens)?,
+                // Resolve's spelling of the domain, the same for all channels
+                "LUT_3D_INPUT_RANGE" => {
+                    let mut bound = || {
+                        tokens
+                            .next()
+                            .and_then(|token| token.parse::<f64>().ok())
+                            .ok_or_else(|| invalid_data("malformed LUT_3D_INPUT_RANGE"))
+                    };
+                    let (min, max) = (bound()?, bound()?);
+                    domain_min = Color::new(min, min, min);
+                    domain_max = Color::new(max, max, max);
+                }
+                _ => table.push(parse_color(line.split_whitespace())?),
+            }
+        }
+
+        let size = size.ok_or_else(|| invalid_data("missing LUT_3D_SIZE"))?;
+        if table.len() != size * size * size {
+            return Err(invalid_data("LUT has the wrong number of entries"));
+        }
+        let span = domain_max - domain_min;
+        if span.x() <= 0.0 || span.y() <= 0.0 || span.z() <= 0.0 {
+            return Err(invalid_data("empty LUT domain"));
+        }
+        Ok(Lut {
+            size,
+            domain_min,
+            domain_max,
+            table,
+        })
+    }
+
+    pub fn apply(&self, c: Color) -> Color {
+        // Position in table cells along each axis
+        let last = (self.size - 1) as f64;
+        let cell = |v: f64, min: f64, max: f64| ((v - min) / (max - min)).clamp(0.0, 1.0) * last;
+        let r = cell(c.x(), self.domain_min.x(), self.domain_max.x());
+        let g = cell(c.y(), self.domain_min.y(), self.domain_max.y());
+        let b = cell(c.z(), self.domain_min.z(), self.domain_max.z());
+
+        let (r0, g0, b0) = (
+            r.floor().min(last - 1.0),
+            g.floor().min(last - 1.0),
+            b.floor().min(last - 1.0),
+        );
+        let (fr, fg, fb) = (r - r0, g - g0, b - b0);
+        let (r0, g0, b0) = (r0 as usize, g0 as usize, b0 as usize);
+        let entry = |dr: usize, dg: usize, db: usize| {
+            self.table[r0 + dr + self.size * (g0 + dg + self.size * (b0 + db))]
+        };
+
+        let lerp = |a: Color, b: Color, t: f64| (1.0 - t) * a + t * b;
+        let c00 = lerp(entry(0, 0, 0), entry(1, 0, 0), fr);
+        let c10 = lerp(entry(0, 1, 0), entry(1, 1, 0), fr);
+        let c01 = lerp(entry(0, 0, 1), entry(1, 0, 1), fr);
+        let c11 = lerp(entry(0, 1, 1), entry(1, 1, 1), fr);
+        lerp(lerp(c00, c10, fg), lerp(c01, c11, fg), fb)
+    }
+}
+
+fn parse_color<'a>(mut tokens: impl Iterator<Item = &'a str>) -> io::Result<Color> {
+    let mut next = || {
+        tokens
+            .next()
+            .and_then(|token| token.parse::<f64>().ok())
+            .ok_or_else(|| invalid_data("malformed LUT entry"))
+    };
+    Ok(Color::new(next()?, next()?, next()?))
+}
+
+fn invalid_data(msg: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Swaps red and blue and drops green, over a domain twice the usual size
+    const SWAP: &str = "# test table
+TITLE \"swap\"
+LUT_3D_SIZE 2
+DOMAIN_MIN 0 0 0
+DOMAIN_MAX 2 2 2
+0 0 0
+0 0 2
+0 0 0
+0 0 2
+2 0 0
+2 0 2
+2 0 0
+2 0 2
+";
+
+    #[test]
+    fn interpolates_within_the_domain() {
+        let lut = Lut::parse(SWAP).unwrap();
+        let c = lut.apply(Color::new(0.5, 0.3, 1.5));
+        assert!((c.x() - 1.5).abs() < 1.0e-12);
+        assert!(c.y().abs() < 1.0e-12);
+        assert!((c.z() - 0.5).abs() < 1.0e-12);
+        // Clamped outside it
+        assert!((lut.apply(Color::new(-1.0, 0.0, 5.0)).x() - 2.0).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn rejects_malformed_tables() {
+        assert!(Lut::parse("LUT_3D_SIZE 2\n0 0 0\n").is_err());
+        assert!(Lut::parse("LUT_1D_SIZE 2\n0 0 0\n1 1 1\n").is_err());
+        assert!(Lut::parse("0 0 0\n").is_err());
+        assert!(Lut::parse(&SWAP.replace("2 0 2\n2 0 0", "2 x 2\n2 0 0")).is_err());
+    }
+}
@@ -1,21 +1,73 @@
+mod aov;
+mod aperture;
 mod camera;
 mod color;
+mod colorspace;
 mod common;
+mod compare;
+mod denoise;
+mod display;
+mod film;
+mod filter;
 mod hittable;
 mod hittable_list;
+mod ies;
+mod image;
+mod lens_system;
+mod light;
+mod lut;
 mod material;
+mod normal_map;
+mod options;
+mod perlin;
+mod post;
+mod progress;
+mod projection;
+mod quad;
 mod ray;
+#[cfg(test)]
+mod regression;
+mod sampler;
+mod scene;
 mod sphere;
+mod stereo;
+mod texture;
+mod tile;
+mod triangle;
 mod vec3;
 
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::process;
 use std::sync::Arc;
+use std::thread;
 
-use crate::camera::Camera;
+use crate::aperture::{Aperture, ApertureMask};
+use crate::camera::{AdaptiveSampling, Camera, Lens, PhysicalCamera};
 
 use crate::color::Color;
-use crate::material::{Dielectric, Lambertian, Metal};
+use crate::compare::Metrics;
+use crate::denoise::Features;
+use crate::display::DisplayTransform;
+use crate::film::Film;
+use crate::hittable::AlphaMask;
+use crate::ies::IesProfile;
+use crate::image::Image;
+use crate::lens_system::LensSystem;
+use crate::light::{DirectionalLight, PointLight, SpotLight};
+use crate::lut::Lut;
+use crate::material::{Coated, Dielectric, DiffuseLight, Lambertian, Metal, MixMaterial};
+use crate::normal_map::{BumpMap, Detailed, NormalMap};
+use crate::options::{CompareOptions, Options};
+use crate::progress::{CancelToken, Progress};
+use crate::projection::Projection;
+use crate::quad::Quad;
+use crate::scene::{Scene, SceneKind};
 use crate::sphere::Sphere;
-use vec3::Point3;
+use crate::texture::{CheckerTexture, ImageTexture, NoiseTexture};
+use crate::triangle::Triangle;
+use vec3::{Point3, Vec3};
 
 use hittable_list::HittableList;
 
@@ -24,22 +76,412 @@ const IMAGE_WIDTH: i32 = 1200;
 const SAMPLES_PER_PIXEL: i32 = 500;
 
 fn main() {
+    let mut args = std::env::args().skip(1).peekable();
+    if args.next_if(|arg| arg == "compare").is_some() {
+        let options = CompareOptions::parse(args).unwrap_or_else(|err| {
+            eprintln!("{err}\n\n{}", options::USAGE);
+            process::exit(2);
+        });
+        compare_images(&options);
+        return;
+    }
+    let options = Options::parse(args).unwrap_or_else(|err| {
+        eprintln!("{err}\n\n{}", options::USAGE);
+        process::exit(2);
+    });
+
+    let seed = options.seed.unwrap_or_else(rand::random);
+    eprintln!("Seed: {seed}");
+
     // World
-    let world = final_scence();
+    colorspace::set_working(options.color_space);
+    common::seed_thread(seed);
+    let (scene, view) = build_scene(options.scene, Path::new("."));
+
+    // Panoramas cover 360 by 180 degrees
+    let aspect_ratio = match options.projection {
+        Projection::Equirectangular => 2.0,
+        _ => ASPECT_RATIO,
+    };
+    let aperture = load_aperture(&options);
+    let lut = options.lut.as_deref().map(load_lut);
+    let mut physical = options.physical;
+    let mut lens_system = traced_lens(&options, physical.as_mut(), aspect_ratio, &aperture);
+    // A photographic camera replaces the lens but keeps the viewpoint
+    let framed = |lens: Lens| Lens {
+        look_from: view.look_from,
+        look_at: view.look_at,
+        ..lens
+    };
+    let mut lens = physical.map_or(view, |physical| framed(physical.lens(aspect_ratio)));
+    if let Some(focus) = &options.focus {
+        let probe = Camera::new(aspect_ratio, IMAGE_WIDTH, 1, &lens);
+        let distance = probe.autofocus(&scene, focus).unwrap_or_else(|err| {
+            eprintln!("cannot focus: {err}");
+            process::exit(1);
+        });
+        eprintln!("Focus distance: {distance:.3}");
+        lens.focus_dist = distance;
+        if let Some(physical) = physical.as_mut() {
+            physical.focus_distance = distance;
+            lens = framed(physical.lens(aspect_ratio));
+            lens_system = traced_lens(&options, Some(physical), aspect_ratio, &aperture);
+        }
+    }
+    if let Some(lens_system) = &lens_system {
+        eprintln!("Lens: {}", lens_system.describe());
+    }
+    let mut camera = Camera::new(aspect_ratio, IMAGE_WIDTH, options.samples_per_pixel, &lens);
+    camera.aperture = aperture;
+    camera.lens_system = lens_system;
+    camera.stereo = options.stereo;
+    if let Some(physical) = &physical {
+        camera.exposure = physical.exposure();
+        eprintln!(
+            "Field of view {:.1} degrees, exposure {:.3}",
+            lens.vfov, camera.exposure
+        );
+    }
+    if let Some(kelvin) = options.white_balance {
+        camera.white_balance = Some(colorspace::white_balance(kelvin, options.color_space));
+    }
+    camera.projection = options.projection;
+    camera.tile_size = options.tile_size;
+    camera.tile_order = options.tile_order;
+    camera.seed = seed;
+    camera.sampler = options.sampler;
+    camera.filter = options.filter();
+    camera.aovs = options.aovs.is_some() || options.denoise;
+    camera.max_depth = options.max_depth();
+    camera.roulette_depth = options.roulette_depth;
+    camera.clamp = options.clamp;
+
+    // Pressing Enter stops the render early; finished tiles are still written
+    let cancel = CancelToken::new();
+    let stop = cancel.clone();
+    thread::spawn(move || {
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line).is_ok_and(|n| n > 0) {
+            stop.cancel();
+        }
+    });
+
+    let mut film = load_film(&camera, &options);
+    if let Some(pass_samples) = options.pass_samples {
+        let checkpoint = options.checkpoint.as_deref();
+        if let Err(err) = camera.render_progressive(
+            &scene,
+            &mut film,
+            pass_samples,
+            checkpoint,
+            &report_progress,
+            &cancel,
+        ) {
+            eprintln!("\nfailed to write checkpoint: {err}");
+            process::exit(1);
+        }
+    } else if let Some(threshold) = options.adaptive_threshold {
+        let adaptive = AdaptiveSampling {
+            min_samples: options.min_samples,
+            max_samples: options
+                .max_samples
+                .unwrap_or_else(|| (4 * options.samples_per_pixel as u64).max(options.min_samples)),
+            threshold,
+        };
+        camera.render_adaptive(&scene, &mut film, &adaptive, &report_progress, &cancel);
+    } else if let Some(time_limit) = options.time_limit {
+        let samples = camera.render_timed(&scene, &mut film, time_limit, &report_progress, &cancel);
+        eprint!(
+            "\nReached {samples} samples per pixel within {:.1}s",
+            time_limit.as_secs_f64()
+        );
+    } else {
+        film = camera.render(&scene, &report_progress, &cancel);
+    }
+
+    eprint!(
+        "\n{:.1} samples per pixel on average",
+        film.total_samples() as f64 / (film.width() * film.height()) as f64
+    );
+    eprint!("\nDone\n");
+
+    if let Some(path) = &options.heatmap {
+        // Only the image on stdout is tone mapped and graded; the heatmap
+        // just gets the sRGB encoding
+        let display = DisplayTransform::default();
+        let written = File::create(path).and_then(|file| {
+            color::write_image(&mut BufWriter::new(file), &film.sample_heatmap(), &display)
+        });
+        if let Err(err) = written {
+            eprintln!("failed to write {}: {err}", path.display());
+        }
+    }
+    let layers = film.aovs().map(|aovs| aovs.resolve()).unwrap_or_default();
+    if let Some(prefix) = &options.aovs {
+        for (name, layer) in aov::LAYERS.iter().zip(&layers) {
+            let mut path = prefix.clone().into_os_string();
+            path.push(format!(".{name}.pfm"));
+            let written = File::create(&path)
+                .and_then(|file| color::write_pfm(&mut BufWriter::new(file), layer));
+            if let Err(err) = written {
+                eprintln!("failed to write {}: {err}", path.display());
+            }
+        }
+    }
+
+    let mut image = film.resolve();
+    if let Some(factor) = options.firefly_factor {
+        image = post::per_eye(&image, options.stereo.as_ref(), |part, _| {
+            post::remove_fireflies(part, factor)
+        });
+    }
+    if options.denoise && film.aovs().is_some_and(|aovs| aovs.is_empty()) {
+        eprintln!("no samples were taken in this run to guide the denoiser; not denoising");
+    } else if options.denoise {
+        // The à-trous passes reach far enough to cross a stereo seam
+        image = post::per_eye(&image, options.stereo.as_ref(), |part, region| {
+            let variance: Vec<f64> = (region.y0..region.y1)
+                .flat_map(|y| (region.x0..region.x1).map(move |x| (x, y)))
+                .map(|(x, y)| film.variance(x, y))
+                .collect();
+            let features = Features {
+                albedo: &post::crop(&layers[aov::ALBEDO], region),
+                normal: &post::crop(&layers[aov::NORMAL], region),
+                depth: &post::crop(&layers[aov::DEPTH], region),
+                variance: &variance,
+            };
+            denoise::denoise(part, &features)
+        });
+    }
+    image = options.post.apply(&image, seed, options.stereo.as_ref());
+    if let Some(path) = &options.hdr {
+        let written =
+            File::create(path).and_then(|file| color::write_pfm(&mut BufWriter::new(file), &image));
+        if let Err(err) = written {
+            eprintln!("failed to write {}: {err}", path.display());
+        }
+    }
+    let mut display = options.display.clone();
+    display.lut = lut;
+    let mut out = BufWriter::new(io::stdout().lock());
+    if let Err(err) = color::write_image(&mut out, &image, &display) {
+        eprintln!("failed to write image: {err}");
+        process::exit(1);
+    }
+}
+
+fn compare_images(options: &CompareOptions) {
+    let load = |path: &Path| {
+        Image::read(path).unwrap_or_else(|err| {
+            eprintln!("cannot read {}: {err}", path.display());
+            process::exit(1);
+        })
+    };
+    let reference = load(&options.reference);
+    let test = load(&options.test);
+    if (reference.width(), reference.height()) != (test.width(), test.height()) {
+        eprintln!(
+            "images differ in size: {}x{} and {}x{}",
+            reference.width(),
+            reference.height(),
+            test.width(),
+            test.height()
+        );
+        process::exit(1);
+    }
+
+    let (metrics, flip) = Metrics::compute(&reference, &test);
+    println!("RMSE    {:.6}", metrics.rmse);
+    println!("relMSE  {:.6}", metrics.rel_mse);
+    println!("PSNR    {:.2} dB", metrics.psnr);
+    println!("SSIM    {:.4}", metrics.ssim);
+    println!("FLIP    {:.4}", metrics.flip);
+
+    if let Some(path) = &options.diff {
+        let mut diff = Image::new(reference.width(), reference.height());
+        for (i, error) in flip.iter().enumerate() {
+            diff.set_pixel(i % diff.width(), i / diff.width(), color::heat(*error));
+        }
+        let written = File::create(path).and_then(|file| {
+            color::write_image(
+                &mut BufWriter::new(file),
+                &diff,
+                &DisplayTransform::default(),
+            )
+        });
+        if let Err(err) = written {
+            eprintln!("failed to write {}: {err}", path.display());
+            process::exit(1);
+        }
+    }
+}
+
+fn load_lut(path: &Path) -> Arc<Lut> {
+    match Lut::open(path) {
+        Ok(lut) => Arc::new(lut),
+        Err(err) => {
+            eprintln!("cannot read LUT {}: {err}", path.display());
+            process::exit(1);
+        }
+    }
+}
+
+fn load_aperture(options: &Options) -> Aperture {
+    if let Some(blades) = options.aperture_blades {
+        return Aperture::Polygon {
+            blades,
+            rotation: options.aperture_rotation,
+        };
+    }
+    let Some(path) = &options.aperture_mask else {
+        return Aperture::Circle;
+    };
+
+    match Image::read_ppm(path) {
+        Ok(image) => {
+            let mask = ApertureMask::new(image);
+            if mask.is_opaque() {
+                eprintln!("aperture mask {} is black", path.display());
+                process::exit(1);
+            }
+            Aperture::Mask(Arc::new(mask))
+        }
+        Err(err) => {
+            eprintln!("cannot read aperture mask {}: {err}", path.display());
+            process::exit(1);
+        }
+    }
+}
+
+/// The lens prescription to trace, if any. The physical camera's focal
+/// length and f-number are updated to the traced lens's.
+fn traced_lens(
+    options: &Options,
+    physical: Option<&mut PhysicalCamera>,
+    aspect_ratio: f64,
+    aperture: &Aperture,
+) -> Option<LensSystem> {
+    let (name, physical) = options.lens.as_ref().zip(physical)?;
+    let lens_system = load_lens_system(name, physical, aspect_ratio, aperture, options);
+    // The exposure and field of view follow the traced lens
+    physical.focal_length = lens_system.focal_length * 1000.0;
+    physical.f_number = lens_system.f_number;
+    Some(lens_system)
+}
+
+fn load_lens_system(
+    name: &str,
+    physical: &PhysicalCamera,
+    aspect_ratio: f64,
+    aperture: &Aperture,
+    options: &Options,
+) -> LensSystem {
+    let prescription = match name {
+        "double-gauss" => lens_system::DOUBLE_GAUSS.to_string(),
+        path => fs::read_to_string(path).unwrap_or_else(|err| {
+            eprintln!("cannot read lens {path}: {err}");
+            process::exit(1);
+        }),
+    };
 
-    let camera = Camera::new(ASPECT_RATIO, IMAGE_WIDTH, SAMPLES_PER_PIXEL);
-    camera.render(&world);
+    let (film_width, film_height) = physical.film_size(aspect_ratio);
+    LensSystem::new(
+        &prescription,
+        film_width / 1000.0,
+        film_height / 1000.0,
+        physical.focus_distance,
+        options.stopped_down.then_some(physical.f_number),
+        aperture.clone(),
+    )
+    .unwrap_or_else(|err| {
+        eprintln!("lens {name}: {err}");
+        process::exit(1);
+    })
+}
+
+fn load_film(camera: &Camera, options: &Options) -> Film {
+    let fresh = camera.film();
+    let Some(path) = options.checkpoint.as_ref().filter(|_| options.resume) else {
+        return fresh;
+    };
+
+    match Film::load(path) {
+        Ok(film) if film.width() == fresh.width() && film.height() == fresh.height() => {
+            eprintln!("Resuming from {} samples per pixel", film.min_samples());
+            // Checkpoints carry no AOVs; they come from the resumed samples only
+            if camera.aovs { film.with_aovs() } else { film }
+        }
+        Ok(_) => {
+            eprintln!("checkpoint {} has a different resolution", path.display());
+            process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("cannot resume from {}: {err}", path.display());
+            process::exit(1);
+        }
+    }
+}
+
+fn report_progress(progress: &Progress) {
+    eprint!(
+        "\rTiles: {}/{}  elapsed: {:.0}s  ETA: {:.0}s  {:.2} Mrays/s   ",
+        progress.tiles_done,
+        progress.tiles_total,
+        progress.elapsed.as_secs_f64(),
+        progress.eta.as_secs_f64(),
+        progress.rays_per_sec / 1.0e6,
+    );
+}
+
+/// Builds the scene `kind` and the view that frames it. Image and IES
+/// files used by some scenes are looked up in `assets`.
+fn build_scene(kind: SceneKind, assets: &Path) -> (Scene, Lens) {
+    let view = |look_from: Point3, look_at: Point3, vfov| Lens {
+        look_from,
+        look_at,
+        vfov,
+        focus_dist: (look_from - look_at).length(),
+        defocus_angle: 0.0,
+    };
+    match kind {
+        SceneKind::Final => (Scene::new(final_scence()), Lens::default()),
+        SceneKind::ThreeSpheres => (
+            Scene::new(three_spheres()),
+            view(
+                Point3::new(0.0, 1.0, 2.0),
+                Point3::new(0.0, 0.0, -1.0),
+                60.0,
+            ),
+        ),
+        SceneKind::CornellBox => (
+            Scene::new(cornell_box()),
+            view(
+                Point3::new(278.0, 278.0, -800.0),
+                Point3::new(278.0, 278.0, 0.0),
+                38.0,
+            ),
+        ),
+        SceneKind::GlassCaustic => (
+            Scene::new(glass_caustic()),
+            view(Point3::new(0.0, 3.0, 6.0), Point3::new(0.0, 0.5, 0.0), 30.0),
+        ),
+        SceneKind::Detail => (Scene::new(detail_scene(assets)), Lens::default()),
+        SceneKind::Lights => (lights_scene(assets), Lens::default()),
+    }
 }
 
 fn final_scence() -> HittableList {
     let mut world = HittableList::new();
 
     let ground_material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
-    world.add(Box::new(Sphere::new(
-        Point3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        ground_material,
-    )));
+    world.add_named(
+        "ground",
+        Box::new(Sphere::new(
+            Point3::new(0.0, -1000.0, 0.0),
+            1000.0,
+            ground_material,
+        )),
+    );
 
     for a in -11..11 {
         for b in -11..11 {
@@ -72,31 +514,52 @@ fn final_scence() -> HittableList {
     }
 
     let material1 = Arc::new(Dielectric::new(1.5));
-    world.add(Box::new(Sphere::new(
-        Point3::new(0.0, 1.0, 0.0),
-        1.0,
-        material1,
-    )));
+    world.add_named(
+        "glass",
+        Box::new(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, material1)),
+    );
 
     let material2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    world.add(Box::new(Sphere::new(
-        Point3::new(-4.0, 1.0, 0.0),
-        1.0,
-        material2,
-    )));
+    world.add_named(
+        "matte",
+        Box::new(Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, material2)),
+    );
 
     let material3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
-    world.add(Box::new(Sphere::new(
-        Point3::new(4.0, 1.0, 0.0),
-        1.0,
-        material3,
-    )));
+    world.add_named(
+        "metal",
+        Box::new(Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, material3)),
+    );
 
     world
 }
 
 #[allow(unused)]
 fn random_scence() -> HittableList {
+    let mut world = three_spheres();
+
+    let r = f64::cos(common::PI / 4.0);
+    let material_left = Arc::new(Lambertian::new(Color::new(0.0, 0.0, 1.0)));
+    let material_right = Arc::new(Lambertian::new(Color::new(1.0, 0.0, 0.0)));
+
+    world.add(Box::new(Sphere::new(
+        Point3::new(-r, 0.0, -1.0),
+        r,
+        material_left,
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(r, 0.0, -1.0),
+        r,
+        material_right,
+    )));
+
+    world
+}
+
+/// The book's first scene: a diffuse, a glass and a metal sphere on a large
+/// ground sphere. Seen from (0, 1, 2) looking at (0, 0, -1) with a 60 degree
+/// field of view.
+fn three_spheres() -> HittableList {
     let mut world = HittableList::new();
 
     let material_ground = Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.0)));
@@ -135,20 +598,301 @@ fn random_scence() -> HittableList {
         material_right,
     )));
 
-    let r = f64::cos(common::PI / 4.0);
-    let material_left = Arc::new(Lambertian::new(Color::new(0.0, 0.0, 1.0)));
-    let material_right = Arc::new(Lambertian::new(Color::new(1.0, 0.0, 0.0)));
+    world
+}
+
+/// Cornell box with a diffuse and a glass sphere. The walls run on past the
+/// open front to close the box behind the camera, so that only the ceiling
+/// light shines in. Seen from (278, 278, -800) looking at (278, 278, 0) with
+/// a 38 degree field of view, which keeps the extra walls out of the frame.
+fn cornell_box() -> HittableList {
+    let mut world = HittableList::new();
+
+    let red = Arc::new(Lambertian::new(Color::new(0.65, 0.05, 0.05)));
+    let white = Arc::new(Lambertian::new(Color::new(0.73, 0.73, 0.73)));
+    let green = Arc::new(Lambertian::new(Color::new(0.12, 0.45, 0.15)));
+    let light = Arc::new(DiffuseLight::new(Color::new(8.0, 8.0, 8.0)));
+
+    let front = -801.0;
+    let side = Vec3::new(0.0, 0.0, 555.0 - front);
+    let up = Vec3::new(0.0, 555.0, 0.0);
+    let across = Vec3::new(555.0, 0.0, 0.0);
+    world.add(Box::new(Quad::new(
+        Point3::new(555.0, 0.0, front),
+        up,
+        side,
+        green,
+    )));
+    world.add(Box::new(Quad::new(
+        Point3::new(0.0, 0.0, front),
+        up,
+        side,
+        red,
+    )));
+    world.add(Box::new(Quad::new(
+        Point3::new(0.0, 0.0, front),
+        across,
+        side,
+        white.clone(),
+    )));
+    world.add(Box::new(Quad::new(
+        Point3::new(0.0, 555.0, front),
+        across,
+        side,
+        white.clone(),
+    )));
+    world.add(Box::new(Quad::new(
+        Point3::new(0.0, 0.0, 555.0),
+        across,
+        up,
+        white.clone(),
+    )));
+    world.add(Box::new(Quad::new(
+        Point3::new(0.0, 0.0, front),
+        across,
+        up,
+        white.clone(),
+    )));
+    world.add(Box::new(Quad::new(
+        Point3::new(185.0, 554.0, 185.0),
+        Vec3::new(185.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 185.0),
+        light,
+    )));
 
     world.add(Box::new(Sphere::new(
-        Point3::new(-r, 0.0, -1.0),
-        r,
-        material_left,
+        Point3::new(170.0, 100.0, 380.0),
+        100.0,
+        white,
     )));
     world.add(Box::new(Sphere::new(
-        Point3::new(r, 0.0, -1.0),
-        r,
-        material_right,
+        Point3::new(390.0, 90.0, 250.0),
+        90.0,
+        Arc::new(Dielectric::new(1.5)),
     )));
 
     world
 }
+
+/// A glass sphere focusing a bright light into a caustic on the floor
+/// beside it. Seen from (0, 3, 6) looking at (0, 0.5, 0) with a 30 degree
+/// field of view.
+fn glass_caustic() -> HittableList {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Dielectric::new(1.5)),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(-2.0, 4.0, -1.0),
+        1.0,
+        Arc::new(DiffuseLight::new(Color::new(8.0, 8.0, 8.0))),
+    )));
+
+    world
+}
+
+/// Textured, normal- and bump-mapped, layered and cut-out surfaces on a
+/// checkered ground, framed like the final scene.
+fn detail_scene(assets: &Path) -> HittableList {
+    let mut world = HittableList::new();
+
+    let checker = Arc::new(CheckerTexture::from_colors(
+        0.32,
+        Color::new(0.2, 0.3, 0.1),
+        Color::new(0.9, 0.9, 0.9),
+    ));
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::from_texture(checker)),
+    )));
+
+    // Procedural bump map over a plain diffuse sphere
+    let bumpy = Arc::new(Detailed::new(
+        Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1))),
+        Arc::new(BumpMap::new(Arc::new(NoiseTexture::new(4.0)), 0.5)),
+    ));
+    world.add(Box::new(Sphere::new(
+        Point3::new(-4.0, 1.0, 0.0),
+        1.0,
+        bumpy,
+    )));
+
+    // Tangent-space normal map on a metal sphere. A missing map leaves the
+    // sphere smooth.
+    let normals = Image::read_ppm(assets.join("normal_map.ppm")).unwrap_or_else(|_| {
+        let mut flat = Image::new(1, 1);
+        flat.set_pixel(0, 0, Color::new(0.5, 0.5, 1.0));
+        flat
+    });
+    let mapped = Arc::new(Detailed::new(
+        Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0)),
+        Arc::new(NormalMap::new(Arc::new(ImageTexture::new(normals)), 1.0)),
+    ));
+    world.add(Box::new(Sphere::new(
+        Point3::new(4.0, 1.0, 0.0),
+        1.0,
+        mapped,
+    )));
+
+    // Dirt over paint, masked by noise, and a clear lacquer over wood
+    let dirty_paint = Arc::new(MixMaterial::from_texture(
+        Arc::new(Metal::new(Color::new(0.8, 0.1, 0.1), 0.2)),
+        Arc::new(Lambertian::new(Color::new(0.3, 0.25, 0.2))),
+        Arc::new(NoiseTexture::new(2.0)),
+    ));
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, 0.5, 2.5),
+        0.5,
+        dirty_paint,
+    )));
+
+    let satin = Arc::new(MixMaterial::new(
+        Arc::new(Lambertian::new(Color::new(0.2, 0.3, 0.6))),
+        Arc::new(Metal::new(Color::new(0.8, 0.8, 0.9), 0.1)),
+        0.25,
+    ));
+    world.add(Box::new(Sphere::new(
+        Point3::new(2.0, 0.5, 2.5),
+        0.5,
+        satin,
+    )));
+
+    let lacquered_wood = Arc::new(Coated::new(
+        Arc::new(Lambertian::new(Color::new(0.45, 0.25, 0.1))),
+        1.5,
+    ));
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, 0.5, -2.5),
+        0.5,
+        lacquered_wood,
+    )));
+
+    let marble = Arc::new(Lambertian::from_texture(Arc::new(NoiseTexture::new(4.0))));
+    world.add(Box::new(Triangle::with_uvs(
+        Point3::new(-1.0, 0.0, -1.0),
+        Point3::new(1.0, 0.0, -1.0),
+        Point3::new(0.0, 2.0, -1.0),
+        [(0.0, 0.0), (1.0, 0.0), (0.5, 1.0)],
+        marble,
+    )));
+
+    // Leaf card and a cutout sphere shell: the checker mask punches holes
+    // through which both camera and scattered rays pass.
+    let holes = Arc::new(AlphaMask::new(
+        Arc::new(CheckerTexture::from_colors(
+            0.25,
+            Color::new(1.0, 1.0, 1.0),
+            Color::new(0.0, 0.0, 0.0),
+        )),
+        0.5,
+    ));
+    let leaf = Arc::new(Lambertian::new(Color::new(0.2, 0.5, 0.1)));
+    world.add(Box::new(
+        Quad::new(
+            Point3::new(2.0, 0.0, -2.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.5, 0.0),
+            leaf.clone(),
+        )
+        .with_alpha_mask(holes.clone()),
+    ));
+    world.add(Box::new(
+        Sphere::new(Point3::new(-2.0, 0.6, -2.0), 0.6, leaf.clone()).with_alpha_mask(holes.clone()),
+    ));
+    world.add(Box::new(
+        Triangle::new(
+            Point3::new(-3.0, 0.0, 2.0),
+            Point3::new(-2.0, 0.0, 2.0),
+            Point3::new(-2.5, 1.5, 2.0),
+            leaf,
+        )
+        .with_alpha_mask(holes),
+    ));
+
+    if let Ok(image) = Image::read_ppm(assets.join("albedo.ppm")) {
+        let albedo = Arc::new(ImageTexture::new(image.into_linear()));
+        world.add(Box::new(Triangle::new(
+            Point3::new(-1.0, 0.0, 1.0),
+            Point3::new(1.0, 0.0, 1.0),
+            Point3::new(-1.0, 2.0, 1.0),
+            Arc::new(Lambertian::from_texture(albedo)),
+        )));
+    }
+
+    world
+}
+
+/// Diffuse and coated spheres lit by point, spot and directional lights
+/// and an emissive quad, framed like the final scene.
+fn lights_scene(assets: &Path) -> Scene {
+    let mut world = HittableList::new();
+
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(-4.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1))),
+    )));
+    world.add(Box::new(Sphere::new(
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        Arc::new(Coated::new(
+            Arc::new(Lambertian::new(Color::new(0.1, 0.2, 0.5))),
+            1.5,
+        )),
+    )));
+
+    // Emissive geometry alongside the analytic lights
+    world.add(Box::new(Quad::new(
+        Point3::new(3.0, 0.5, -1.0),
+        Vec3::new(0.0, 0.0, 2.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0))),
+    )));
+
+    let mut scene = Scene::new(world);
+    scene.add_light(Arc::new(PointLight::new(
+        Point3::new(0.0, 4.0, 3.0),
+        Color::new(10.0, 10.0, 10.0),
+    )));
+
+    // Photometric fixtures, if their IES files are available. Candela values
+    // are scaled into scene units by the light's intensity.
+    let fixture = IesProfile::open(assets.join("fixture.ies"))
+        .ok()
+        .map(Arc::new);
+    let mut spot = SpotLight::new(
+        Point3::new(-4.0, 5.0, 2.0),
+        Point3::new(-4.0, 0.0, 0.0),
+        Color::new(30.0, 25.0, 20.0),
+        30.0,
+        20.0,
+    );
+    if let Some(profile) = &fixture {
+        spot = spot.with_profile(profile.clone());
+        scene.add_light(Arc::new(
+            PointLight::new(Point3::new(4.0, 4.0, 2.0), Color::new(0.01, 0.01, 0.01))
+                .with_profile(profile.clone()),
+        ));
+    }
+    scene.add_light(Arc::new(spot));
+    scene.add_light(Arc::new(DirectionalLight::new(
+        Vec3::new(-1.0, -1.0, -0.5),
+        Color::new(0.3, 0.3, 0.25),
+    )));
+
+    scene
+}
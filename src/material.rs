@@ -1,23 +1,59 @@
-use crate::{color::Color, hittable::HitRecord, ray::Ray};
-use crate::{common, vec3};
+use std::sync::Arc;
+
+use crate::color::{self, Color};
+use crate::texture::{SolidColor, Texture};
+use crate::vec3::{Point3, Vec3};
+use crate::{colorspace, common, sampler, vec3};
+use crate::{hittable::HitRecord, ray::Ray};
+
+/// Which part of a material's scattering a sampled direction came from, so
+/// that renders can split their light into diffuse and specular layers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Lobe {
+    Diffuse,
+    Specular,
+}
 
 pub trait Material: Send + Sync {
+    /// Samples a scattered ray. Returns the lobe it was drawn from, or None
+    /// if the ray was absorbed.
     fn scatter(
         &self,
         r_in: &Ray,
         rec: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
-    ) -> bool;
+    ) -> Option<Lobe>;
+
+    /// BSDF times cosine for light arriving from the unit direction `wi`,
+    /// used for next-event estimation. Purely specular materials cannot be
+    /// lit by delta lights and keep the default of black.
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _wi: Vec3) -> Color {
+        Color::default()
+    }
+
+    fn emitted(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        Color::default()
+    }
+
+    /// Base color of the surface for the albedo layer and the denoiser.
+    /// Clear materials report white, emitters black.
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::default()
+    }
 }
 
 pub struct Lambertian {
-    albedo: Color,
+    tex: Arc<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Color) -> Self {
-        Lambertian { albedo }
+        Self::from_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn from_texture(tex: Arc<dyn Texture>) -> Self {
+        Lambertian { tex }
     }
 }
 
@@ -28,7 +64,7 @@ impl Material for Lambertian {
         rec: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
-    ) -> bool {
+    ) -> Option<Lobe> {
         let mut scatter_direction = rec.normal + vec3::random_unit_vector();
 
         if scatter_direction.near_zero() {
@@ -36,8 +72,20 @@ impl Material for Lambertian {
         }
 
         *scattered = Ray::new(rec.p, scatter_direction);
-        *attenuation = self.albedo;
-        true
+        *attenuation = self.tex.value(rec.u, rec.v, &rec.p);
+        Some(Lobe::Diffuse)
+    }
+
+    fn eval(&self, _r_in: &Ray, rec: &HitRecord, wi: Vec3) -> Color {
+        let cos_theta = vec3::dot(rec.normal, wi);
+        if cos_theta <= 0.0 {
+            return Color::default();
+        }
+        self.tex.value(rec.u, rec.v, &rec.p) * (cos_theta / common::PI)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.tex.value(rec.u, rec.v, &rec.p)
     }
 }
 
@@ -48,7 +96,10 @@ pub struct Metal {
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f64) -> Self {
-        Metal { albedo, fuzz }
+        Metal {
+            albedo: colorspace::input(albedo),
+            fuzz,
+        }
     }
 }
 
@@ -59,12 +110,16 @@ impl Material for Metal {
         rec: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
-    ) -> bool {
+    ) -> Option<Lobe> {
         let mut reflected = vec3::reflect(r_in.direction(), rec.normal);
         reflected = vec3::unit_vector(reflected) + (self.fuzz * vec3::random_unit_vector());
         *scattered = Ray::new(rec.p, reflected);
         *attenuation = self.albedo;
-        vec3::dot(scattered.direction(), rec.normal) > 0.0
+        (vec3::dot(scattered.direction(), rec.normal) > 0.0).then_some(Lobe::Specular)
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
     }
 }
 
@@ -92,7 +147,7 @@ impl Material for Dielectric {
         rec: &HitRecord,
         attenuation: &mut Color,
         scattered: &mut Ray,
-    ) -> bool {
+    ) -> Option<Lobe> {
         *attenuation = Color::new(1.0, 1.0, 1.0);
         let ri = if rec.front_face {
             1.0 / self.refractive_index
@@ -106,15 +161,247 @@ impl Material for Dielectric {
         let sin_theta = f64::sqrt(1.0 - cos_theta * cos_theta);
 
         let cannot_refract = ri * sin_theta > 1.0;
-        let direction;
 
-        if cannot_refract || Self::reflectance(cos_theta, ri) > common::random_double() {
-            direction = vec3::reflect(unit_direction, rec.normal);
+        let direction = if cannot_refract || Self::reflectance(cos_theta, ri) > sampler::get_1d() {
+            vec3::reflect(unit_direction, rec.normal)
         } else {
-            direction = vec3::refract(unit_direction, rec.normal, ri);
-        }
+            vec3::refract(unit_direction, rec.normal, ri)
+        };
 
         *scattered = Ray::new(rec.p, direction);
-        true
+        Some(Lobe::Specular)
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+}
+
+/// Stochastically picks one of two materials per hit. A weight of 0 always
+/// selects `a`, 1 always selects `b`.
+pub struct MixMaterial {
+    a: Arc<dyn Material>,
+    b: Arc<dyn Material>,
+    weight: Arc<dyn Texture>,
+}
+
+impl MixMaterial {
+    pub fn new(a: Arc<dyn Material>, b: Arc<dyn Material>, weight: f64) -> Self {
+        Self::from_texture(
+            a,
+            b,
+            Arc::new(SolidColor::new(Color::new(weight, weight, weight))),
+        )
+    }
+
+    pub fn from_texture(
+        a: Arc<dyn Material>,
+        b: Arc<dyn Material>,
+        weight: Arc<dyn Texture>,
+    ) -> Self {
+        MixMaterial { a, b, weight }
+    }
+
+    fn choose(&self, rec: &HitRecord) -> &Arc<dyn Material> {
+        let w = color::luminance(self.weight.value(rec.u, rec.v, &rec.p));
+        if sampler::get_1d() < w {
+            &self.b
+        } else {
+            &self.a
+        }
+    }
+}
+
+impl Material for MixMaterial {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> Option<Lobe> {
+        self.choose(rec).scatter(r_in, rec, attenuation, scattered)
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: Vec3) -> Color {
+        let w = color::luminance(self.weight.value(rec.u, rec.v, &rec.p)).clamp(0.0, 1.0);
+        (1.0 - w) * self.a.eval(r_in, rec, wi) + w * self.b.eval(r_in, rec, wi)
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let w = color::luminance(self.weight.value(u, v, p)).clamp(0.0, 1.0);
+        (1.0 - w) * self.a.emitted(u, v, p) + w * self.b.emitted(u, v, p)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        let w = color::luminance(self.weight.value(rec.u, rec.v, &rec.p)).clamp(0.0, 1.0);
+        (1.0 - w) * self.a.albedo(rec) + w * self.b.albedo(rec)
+    }
+}
+
+/// A smooth dielectric clear coat over any base material. Light either
+/// reflects off the coat with the Fresnel reflectance, or passes through it,
+/// scatters off the base and loses the Fresnel-reflected part again on the
+/// way out.
+pub struct Coated {
+    base: Arc<dyn Material>,
+    refractive_index: f64,
+}
+
+impl Coated {
+    pub fn new(base: Arc<dyn Material>, refractive_index: f64) -> Self {
+        Coated {
+            base,
+            refractive_index,
+        }
+    }
+}
+
+impl Material for Coated {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> Option<Lobe> {
+        if !rec.front_face {
+            return self.base.scatter(r_in, rec, attenuation, scattered);
+        }
+
+        let unit_direction = vec3::unit_vector(r_in.direction());
+        let cos_in = f64::min(vec3::dot(-unit_direction, rec.normal), 1.0);
+
+        if Dielectric::reflectance(cos_in, self.refractive_index) > sampler::get_1d() {
+            *scattered = Ray::new(rec.p, vec3::reflect(unit_direction, rec.normal));
+            *attenuation = Color::new(1.0, 1.0, 1.0);
+            return Some(Lobe::Specular);
+        }
+
+        let lobe = self.base.scatter(r_in, rec, attenuation, scattered)?;
+        let cos_out = vec3::dot(vec3::unit_vector(scattered.direction()), rec.normal);
+        if cos_out > 0.0 {
+            *attenuation *= 1.0 - Dielectric::reflectance(cos_out, self.refractive_index);
+        }
+        Some(lobe)
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: Vec3) -> Color {
+        let base = self.base.eval(r_in, rec, wi);
+        if !rec.front_face {
+            return base;
+        }
+
+        // Transmission through the coat on the way in and out
+        let cos_in = vec3::dot(wi, rec.normal);
+        let cos_out = vec3::dot(-vec3::unit_vector(r_in.direction()), rec.normal);
+        if cos_in <= 0.0 || cos_out <= 0.0 {
+            return Color::default();
+        }
+        base * (1.0 - Dielectric::reflectance(cos_in, self.refractive_index))
+            * (1.0 - Dielectric::reflectance(cos_out.min(1.0), self.refractive_index))
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.base.emitted(u, v, p)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.base.albedo(rec)
+    }
+}
+
+pub struct DiffuseLight {
+    tex: Arc<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        Self::from_texture(Arc::new(SolidColor::new(emit)))
+    }
+
+    pub fn from_texture(tex: Arc<dyn Texture>) -> Self {
+        DiffuseLight { tex }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _r_in: &Ray,
+        _rec: &HitRecord,
+        _attenuation: &mut Color,
+        _scattered: &mut Ray,
+    ) -> Option<Lobe> {
+        None
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.tex.value(u, v, p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A hit on a floor facing up, seen from straight above.
+    fn floor_hit() -> (Ray, HitRecord) {
+        let rec = HitRecord {
+            normal: Vec3::new(0.0, 1.0, 0.0),
+            front_face: true,
+            ..HitRecord::new()
+        };
+        (
+            Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0)),
+            rec,
+        )
+    }
+
+    #[test]
+    fn mix_at_the_ends_is_one_component() {
+        let (ray, rec) = floor_hit();
+        let a: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.8, 0.1, 0.1)));
+        let b: Arc<dyn Material> = Arc::new(Lambertian::new(Color::new(0.1, 0.1, 0.8)));
+        let wi = vec3::unit_vector(Vec3::new(0.3, 1.0, 0.2));
+        for (weight, component) in [(0.0, &a), (1.0, &b)] {
+            let mix = MixMaterial::new(a.clone(), b.clone(), weight);
+            let difference = mix.eval(&ray, &rec, wi) - component.eval(&ray, &rec, wi);
+            assert!(difference.length() < 1.0e-12);
+
+            let expected = component.albedo(&rec);
+            for _ in 0..100 {
+                let mut attenuation = Color::default();
+                let mut scattered = Ray::default();
+                mix.scatter(&ray, &rec, &mut attenuation, &mut scattered);
+                assert!((attenuation - expected).length() < 1.0e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn coat_reflects_the_fresnel_fraction_at_normal_incidence() {
+        let (ray, rec) = floor_hit();
+        let base = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let coated = Coated::new(base.clone(), 1.5);
+        let r0 = f64::powi((1.0 - 1.5) / (1.0 + 1.5), 2);
+
+        common::seed_thread(1);
+        let trials = 100_000;
+        let mut reflected = 0;
+        for _ in 0..trials {
+            let mut attenuation = Color::default();
+            let mut scattered = Ray::default();
+            if coated.scatter(&ray, &rec, &mut attenuation, &mut scattered) == Some(Lobe::Specular)
+            {
+                reflected += 1;
+            }
+        }
+        assert!((reflected as f64 / trials as f64 - r0).abs() < 0.003);
+
+        // Light from straight above crosses the coat twice
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let expected = (1.0 - r0) * (1.0 - r0) * base.eval(&ray, &rec, up);
+        assert!((coated.eval(&ray, &rec, up) - expected).length() < 1.0e-12);
     }
 }
@@ -0,0 +1,165 @@
+use std::sync::Arc;
+
+use crate::color::{self, Color};
+use crate::hittable::HitRecord;
+use crate::material::{Lobe, Material};
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::vec3::{self, Point3, Vec3};
+
+/// Adjusts the shading normal of a hit before its material scatters.
+pub trait SurfaceDetail: Send + Sync {
+    fn perturb(&self, rec: &mut HitRecord);
+}
+
+/// Tangent-space normal map: RGB in [0, 1] encodes a normal in the
+/// (tangent, bitangent, normal) frame of the hit.
+pub struct NormalMap {
+    texture: Arc<dyn Texture>,
+    strength: f64,
+}
+
+impl NormalMap {
+    pub fn new(texture: Arc<dyn Texture>, strength: f64) -> Self {
+        NormalMap { texture, strength }
+    }
+}
+
+impl SurfaceDetail for NormalMap {
+    fn perturb(&self, rec: &mut HitRecord) {
+        let c = self.texture.value(rec.u, rec.v, &rec.p);
+        let x = self.strength * (2.0 * c.x() - 1.0);
+        let y = self.strength * (2.0 * c.y() - 1.0);
+        let z = 2.0 * c.z() - 1.0;
+
+        let n = rec.outwards_normal();
+        let mapped = x * rec.tangent + y * rec.bitangent + z * n;
+        if mapped.near_zero() {
+            return;
+        }
+        set_shading_normal(rec, vec3::unit_vector(mapped));
+    }
+}
+
+/// Bump map driven by the luminance of any texture, image or procedural.
+/// The height gradient is estimated with forward differences along the
+/// tangent frame.
+pub struct BumpMap {
+    height: Arc<dyn Texture>,
+    scale: f64,
+}
+
+impl BumpMap {
+    const DELTA: f64 = 1.0e-3;
+
+    pub fn new(height: Arc<dyn Texture>, scale: f64) -> Self {
+        BumpMap { height, scale }
+    }
+}
+
+impl SurfaceDetail for BumpMap {
+    fn perturb(&self, rec: &mut HitRecord) {
+        let d = Self::DELTA;
+        let h = |du: f64, dv: f64, offset: Vec3| {
+            color::luminance(self.height.value(rec.u + du, rec.v + dv, &(rec.p + offset)))
+        };
+
+        let h0 = h(0.0, 0.0, Vec3::default());
+        let dhdu = (h(d, 0.0, d * rec.tangent) - h0) / d;
+        let dhdv = (h(0.0, d, d * rec.bitangent) - h0) / d;
+
+        let n = rec.outwards_normal();
+        let bumped = n - self.scale * (dhdu * rec.tangent + dhdv * rec.bitangent);
+        if bumped.near_zero() {
+            return;
+        }
+        set_shading_normal(rec, vec3::unit_vector(bumped));
+    }
+}
+
+fn set_shading_normal(rec: &mut HitRecord, outwards_normal: Vec3) {
+    rec.normal = if rec.front_face {
+        outwards_normal
+    } else {
+        -outwards_normal
+    };
+}
+
+/// Wraps a material so that its surface detail is applied to the hit record
+/// before the inner material scatters.
+pub struct Detailed {
+    base: Arc<dyn Material>,
+    detail: Arc<dyn SurfaceDetail>,
+}
+
+impl Detailed {
+    pub fn new(base: Arc<dyn Material>, detail: Arc<dyn SurfaceDetail>) -> Self {
+        Detailed { base, detail }
+    }
+}
+
+impl Material for Detailed {
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        attenuation: &mut Color,
+        scattered: &mut Ray,
+    ) -> Option<Lobe> {
+        let mut shading_rec = rec.clone();
+        self.detail.perturb(&mut shading_rec);
+        self.base
+            .scatter(r_in, &shading_rec, attenuation, scattered)
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, wi: Vec3) -> Color {
+        let mut shading_rec = rec.clone();
+        self.detail.perturb(&mut shading_rec);
+        self.base.eval(r_in, &shading_rec, wi)
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: &Point3) -> Color {
+        self.base.emitted(u, v, p)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.base.albedo(rec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::Hittable;
+    use crate::image::Image;
+    use crate::material::Lambertian;
+    use crate::sphere::Sphere;
+    use crate::texture::ImageTexture;
+
+    fn normal_map(c: Color) -> NormalMap {
+        let mut image = Image::new(1, 1);
+        image.set_pixel(0, 0, c);
+        NormalMap::new(Arc::new(ImageTexture::new(image)), 1.0)
+    }
+
+    #[test]
+    fn flat_normal_map_keeps_the_normal() {
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, material);
+        // From outside and from inside the sphere
+        for origin in [Point3::new(0.3, 0.4, 5.0), Point3::new(0.1, -0.2, 0.0)] {
+            let mut rec = HitRecord::new();
+            let ray = Ray::new(origin, Vec3::new(0.0, 0.1, -1.0));
+            assert!(sphere.hit(&ray, 0.001, f64::INFINITY, &mut rec));
+
+            let mut flat = rec.clone();
+            normal_map(Color::new(0.5, 0.5, 1.0)).perturb(&mut flat);
+            assert!((flat.normal - rec.normal).length() < 1.0e-12);
+
+            // Red leans the normal towards the tangent
+            let mut tilted = rec.clone();
+            normal_map(Color::new(1.0, 0.5, 1.0)).perturb(&mut tilted);
+            assert!(vec3::dot(tilted.outwards_normal(), rec.tangent) > 0.5);
+        }
+    }
+}
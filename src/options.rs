@@ -0,0 +1,657 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::SAMPLES_PER_PIXEL;
+use crate::camera::{Focus, PhysicalCamera};
+use crate::colorspace::ColorSpace;
+use crate::display::{DisplayTransform, ToneMap};
+use crate::filter::{Filter, FilterKind};
+use crate::post::{Bloom, Glare, PostEffects};
+use crate::projection::{FisheyeMapping, Projection};
+use crate::sampler::SamplerKind;
+use crate::scene::SceneKind;
+use crate::stereo::{Stereo, StereoLayout};
+use crate::tile::TileOrder;
+
+/// Command line settings for a render.
+pub struct Options {
+    pub scene: SceneKind,
+    pub samples_per_pixel: i32,
+    pub projection: Projection,
+    // Set by any of the photographic camera options
+    pub physical: Option<PhysicalCamera>,
+    // Sets the focus distance from the scene
+    pub focus: Option<Focus>,
+    // Blade count of a polygonal aperture; None keeps it round
+    pub aperture_blades: Option<u32>,
+    // Angle of the first blade corner in degrees
+    pub aperture_rotation: f64,
+    pub aperture_mask: Option<PathBuf>,
+    // "double-gauss" or a prescription file; traced instead of the thin lens
+    pub lens: Option<String>,
+    // Whether --f-stop was given, which closes a traced lens down
+    pub stopped_down: bool,
+    pub stereo: Option<Stereo>,
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
+    // Samples per pixel per progressive pass; None renders in a single pass
+    pub pass_samples: Option<u64>,
+    pub checkpoint: Option<PathBuf>,
+    pub resume: bool,
+    // Convergence threshold; None samples every pixel equally
+    pub adaptive_threshold: Option<f64>,
+    pub min_samples: u64,
+    pub max_samples: Option<u64>,
+    pub heatmap: Option<PathBuf>,
+    // Path prefix for the AOV layers; None skips them
+    pub aovs: Option<PathBuf>,
+    pub denoise: bool,
+    // None uses 50 bounces, or ROULETTE_MAX_DEPTH with Russian roulette
+    pub max_depth: Option<i32>,
+    pub roulette_depth: Option<i32>,
+    pub clamp: Option<f64>,
+    pub firefly_factor: Option<f64>,
+    // Space the renderer computes in
+    pub color_space: ColorSpace,
+    // Color temperature in kelvin of the light that should come out neutral
+    pub white_balance: Option<f64>,
+    pub post: PostEffects,
+    // Grading LUT applied to the display colors
+    pub lut: Option<PathBuf>,
+    // How the image written to stdout is turned into display colors
+    pub display: DisplayTransform,
+    // Path for a linear copy of the final image
+    pub hdr: Option<PathBuf>,
+    pub time_limit: Option<Duration>,
+    // None picks a fresh seed for every run
+    pub seed: Option<u64>,
+    pub sampler: SamplerKind,
+    pub filter: FilterKind,
+    // None uses the filter's default radius
+    pub filter_radius: Option<f64>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            scene: SceneKind::default(),
+            samples_per_pixel: SAMPLES_PER_PIXEL,
+            projection: Projection::default(),
+            physical: None,
+            focus: None,
+            aperture_blades: None,
+            aperture_rotation: 0.0,
+            aperture_mask: None,
+            lens: None,
+            stopped_down: false,
+            stereo: None,
+            tile_size: 32,
+            tile_order: TileOrder::default(),
+            pass_samples: None,
+            checkpoint: None,
+            resume: false,
+            adaptive_threshold: None,
+            min_samples: 16,
+            max_samples: None,
+            heatmap: None,
+            aovs: None,
+            denoise: false,
+            max_depth: None,
+            roulette_depth: None,
+            clamp: None,
+            firefly_factor: None,
+            color_space: ColorSpace::default(),
+            white_balance: None,
+            post: PostEffects::default(),
+            lut: None,
+            display: DisplayTransform::default(),
+            hdr: None,
+            time_limit: None,
+            seed: None,
+            sampler: SamplerKind::default(),
+            filter: FilterKind::default(),
+            filter_radius: None,
+        }
+    }
+}
+
+pub const USAGE: &str = "usage: raytracer [options] > image.ppm
+       raytracer compare <reference> <test> [--diff <file.ppm>]
+
+options:
+  --scene <final|three-spheres|cornell|caustic|detail|lights>
+                                        scene to render (default final); detail and lights
+                                        read normal_map.ppm, albedo.ppm and fixture.ies
+                                        from the working directory if they exist
+  --spp <samples>                       samples per pixel (default 500)
+  --projection <perspective|orthographic|fisheye|fisheye-equisolid|equirectangular>
+                                        how the view maps to the image; fisheye is
+                                        equidistant, equirectangular renders a 2:1 panorama
+  --fov <degrees>                       fisheye: angle covered by the image circle (default 180)
+  --ortho-height <units>                orthographic: height of the view in world units
+                                        (default: the view at the focus distance)
+  --focal-length <mm>                   photographic camera: lens focal length (default 50)
+  --sensor <width>x<height>             sensor size in mm (default 36x24)
+  --f-stop <number>                     aperture as an f-number (default 16)
+  --focus-distance <meters>             distance in focus (default 10)
+  --shutter <seconds>                   shutter time, e.g. 1/250 (default 1/100)
+  --iso <number>                        sensor sensitivity (default 100); shutter, f-stop
+                                        and ISO set the exposure, which is 1 at f/16,
+                                        1/100 s and ISO 100
+  --focus <look-at|x,y|name>            focus on the point looked at, on what pixel x,y
+                                        (from the top left) shows, or on a named object:
+                                        ground, glass, matte or metal
+  --aperture-blades <count>             polygonal aperture with this many blades
+  --aperture-rotation <degrees>         angle of the first blade corner (default 0)
+  --aperture-mask <file.ppm>            aperture shaped like the bright parts of an image
+  --lens <double-gauss|file>            trace rays through a lens prescription: one line per
+                                        surface, front to rear, with radius, thickness,
+                                        index of refraction and aperture diameter in mm;
+                                        the lens is wide open unless --f-stop is given
+  --stereo <side-by-side|top-bottom|ods>
+                                        render a left and right eye image into one image;
+                                        ods is an omni-directional stereo panorama, stacked
+  --interocular <units>                 stereo: distance between the eyes (default 0.064)
+  --convergence <units>                 stereo: distance at which the eye images coincide
+                                        (default: the focus distance); not with --lens
+  --seed <number>                       seed for the scene layout and sampling; the same
+                                        seed reproduces the same image exactly
+  --sampler <independent|stratified|halton|sobol>
+                                        sample pattern for pixel, lens and material
+                                        dimensions (default independent)
+  --filter <box|tent|gaussian|mitchell|lanczos>
+                                        pixel reconstruction filter (default box)
+  --filter-radius <pixels>              filter radius (defaults 0.5, 1, 1.5, 2 and 3)
+  --tile-size <pixels>                  edge length of a render tile (default 32)
+  --tile-order <spiral|hilbert|scanline>
+                                        order in which tiles are rendered
+  --progressive <samples>               render in passes of this many samples per pixel
+  --checkpoint <file>                   save the accumulated film here after every pass
+  --resume                              continue from the film in the checkpoint file
+  --adaptive <threshold>                stop sampling pixels whose relative error is below
+                                        the threshold and spend the budget on noisy ones
+  --min-spp <samples>                   adaptive: samples every pixel takes, at least 2
+                                        (default 16)
+  --max-spp <samples>                   adaptive: most samples for one pixel, at least
+                                        --min-spp (default 4x --spp)
+  --heatmap <file.ppm>                  write a map of the samples taken per pixel
+  --max-depth <bounces>                 longest path traced (default 50, or 256 with
+                                        --roulette)
+  --roulette <bounces>                  end paths at random after this many bounces,
+                                        weighting survivors up (unbiased)
+  --clamp <luminance>                   scale down brighter samples to remove fireflies
+                                        (biased: the image gets darker)
+  --firefly-filter <factor>             replace pixels more than factor times brighter
+                                        than all their neighbours (biased)
+  --aovs <prefix>                       write depth, normal, position, albedo, ID and
+                                        per-lobe lighting layers to <prefix>.<layer>.pfm
+  --denoise                             filter the noise out of the image, guided by the
+                                        albedo, normal and depth layers
+  --color-space <rec709|acescg|rec2020>
+                                        working space for rendering (default rec709)
+  --white-balance <kelvin>              make light of this color temperature neutral
+  --bloom <threshold[,strength[,radius]]>
+                                        glow around pixels brighter than the threshold
+                                        (default strength 0.1, radius 0.01 of the width)
+  --glare <threshold[,strength[,streaks[,length]]]>
+                                        star-shaped streaks from bright pixels (default
+                                        strength 0.05, 4 streaks, length 0.05 of the width)
+  --chromatic-aberration <amount>       color fringes towards the edges, e.g. 0.002
+  --vignette <strength>                 darken the corners by the cos^4 law, e.g. 0.5
+  --grain <amount>                      film grain, relative to the pixel value
+  --lut <file.cube>                     grade the written image with a 3D LUT
+  --exposure-comp <stops>               brighten or darken the written image (default 0)
+  --tonemap <clip|reinhard|hable|aces>  how highlights are brought into the display range
+                                        (default clip)
+  --hdr <file.pfm>                      also write the final image as linear floats in the
+                                        working space, before exposure compensation and
+                                        tone mapping
+  --time-limit <seconds>                sample the whole image uniformly until the time is
+                                        up, ignoring --spp
+
+compare reports RMSE, relMSE, PSNR, SSIM and FLIP between two PPM, PNG, PFM or EXR
+images, and with --diff writes a map of the FLIP error from blue (none) to red.
+";
+
+// Safety net for Russian roulette, which ends almost every path long before
+const ROULETTE_MAX_DEPTH: i32 = 256;
+
+impl Options {
+    pub fn max_depth(&self) -> i32 {
+        match (self.max_depth, self.roulette_depth) {
+            (Some(depth), _) => depth,
+            (None, Some(_)) => ROULETTE_MAX_DEPTH,
+            (None, None) => 50,
+        }
+    }
+
+    pub fn filter(&self) -> Filter {
+        Filter::new(
+            self.filter,
+            self.filter_radius
+                .unwrap_or_else(|| self.filter.default_radius()),
+        )
+    }
+
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        let mut projection = "perspective".to_string();
+        let mut fov = 180.0;
+        let mut ortho_height = None;
+        let mut focus_distance = false;
+        let mut stereo = None;
+        let mut interocular = None;
+        let mut convergence = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {arg}"))
+            };
+            match arg.as_str() {
+                "--scene" => {
+                    options.scene = match value()?.as_str() {
+                        "final" => SceneKind::Final,
+                        "three-spheres" => SceneKind::ThreeSpheres,
+                        "cornell" => SceneKind::CornellBox,
+                        "caustic" => SceneKind::GlassCaustic,
+                        "detail" => SceneKind::Detail,
+                        "lights" => SceneKind::Lights,
+                        other => return Err(format!("unknown scene '{other}'")),
+                    }
+                }
+                "--spp" => options.samples_per_pixel = parse_number(&value()?)?,
+                "--projection" => projection = value()?,
+                "--fov" => fov = parse_number(&value()?)?,
+                "--ortho-height" => ortho_height = Some(parse_number(&value()?)?),
+                "--focal-length" => physical(&mut options).focal_length = parse_number(&value()?)?,
+                "--sensor" => {
+                    let size = value()?;
+                    let (width, height) = size
+                        .split_once('x')
+                        .ok_or_else(|| format!("invalid sensor size '{size}'"))?;
+                    let camera = physical(&mut options);
+                    camera.sensor_width = parse_number(width)?;
+                    camera.sensor_height = parse_number(height)?;
+                }
+                "--f-stop" => {
+                    physical(&mut options).f_number = parse_number(&value()?)?;
+                    options.stopped_down = true;
+                }
+                "--focus" => options.focus = Some(parse_focus(&value()?)?),
+                "--aperture-blades" => options.aperture_blades = Some(parse_number(&value()?)?),
+                "--aperture-rotation" => options.aperture_rotation = parse_number(&value()?)?,
+                "--aperture-mask" => options.aperture_mask = Some(PathBuf::from(value()?)),
+                "--lens" => {
+                    options.lens = Some(value()?);
+                    physical(&mut options);
+                }
+                "--focus-distance" => {
+                    physical(&mut options).focus_distance = parse_number(&value()?)?;
+                    focus_distance = true;
+                }
+                "--shutter" => {
+                    let shutter = value()?;
+                    physical(&mut options).shutter = match shutter.split_once('/') {
+                        Some((n, d)) => parse_number::<f64>(n)? / parse_number::<f64>(d)?,
+                        None => parse_number(&shutter)?,
+                    }
+                }
+                "--iso" => physical(&mut options).iso = parse_number(&value()?)?,
+                "--stereo" => stereo = Some(value()?),
+                "--interocular" => interocular = Some(parse_number(&value()?)?),
+                "--convergence" => convergence = Some(parse_number(&value()?)?),
+                "--seed" => options.seed = Some(parse_number(&value()?)?),
+                "--filter" => {
+                    options.filter = match value()?.as_str() {
+                        "box" => FilterKind::Box,
+                        "tent" => FilterKind::Tent,
+                        "gaussian" => FilterKind::Gaussian,
+                        "mitchell" => FilterKind::Mitchell,
+                        "lanczos" => FilterKind::Lanczos,
+                        other => return Err(format!("unknown filter '{other}'")),
+                    }
+                }
+                "--filter-radius" => options.filter_radius = Some(parse_number(&value()?)?),
+                "--sampler" => {
+                    options.sampler = match value()?.as_str() {
+                        "independent" => SamplerKind::Independent,
+                        "stratified" => SamplerKind::Stratified,
+                        "halton" => SamplerKind::Halton,
+                        "sobol" => SamplerKind::Sobol,
+                        other => return Err(format!("unknown sampler '{other}'")),
+                    }
+                }
+                "--tile-size" => options.tile_size = parse_number(&value()?)?,
+                "--tile-order" => {
+                    options.tile_order = match value()?.as_str() {
+                        "spiral" => TileOrder::Spiral,
+                        "hilbert" => TileOrder::Hilbert,
+                        "scanline" => TileOrder::Scanline,
+                        other => return Err(format!("unknown tile order '{other}'")),
+                    }
+                }
+                "--progressive" => options.pass_samples = Some(parse_number(&value()?)?),
+                "--checkpoint" => options.checkpoint = Some(PathBuf::from(value()?)),
+                "--resume" => options.resume = true,
+                "--adaptive" => options.adaptive_threshold = Some(parse_number(&value()?)?),
+                "--min-spp" => options.min_samples = parse_number(&value()?)?,
+                "--max-spp" => options.max_samples = Some(parse_number(&value()?)?),
+                "--heatmap" => options.heatmap = Some(PathBuf::from(value()?)),
+                "--aovs" => options.aovs = Some(PathBuf::from(value()?)),
+                "--denoise" => options.denoise = true,
+                "--max-depth" => options.max_depth = Some(parse_number(&value()?)?),
+                "--roulette" => options.roulette_depth = Some(parse_number(&value()?)?),
+                "--clamp" => options.clamp = Some(parse_number(&value()?)?),
+                "--firefly-filter" => options.firefly_factor = Some(parse_number(&value()?)?),
+                "--color-space" => {
+                    options.color_space = match value()?.as_str() {
+                        "rec709" => ColorSpace::Rec709,
+                        "acescg" => ColorSpace::AcesCg,
+                        "rec2020" => ColorSpace::Rec2020,
+                        other => return Err(format!("unknown color space '{other}'")),
+                    }
+                }
+                "--white-balance" => {
+                    let kelvin: f64 = parse_number(&value()?)?;
+                    if !(1667.0..=25000.0).contains(&kelvin) {
+                        return Err(format!(
+                            "white balance '{kelvin}' is outside 1667K to 25000K"
+                        ));
+                    }
+                    options.white_balance = Some(kelvin);
+                }
+                "--bloom" => options.post.bloom = Some(parse_bloom(&value()?)?),
+                "--glare" => options.post.glare = Some(parse_glare(&value()?)?),
+                "--chromatic-aberration" => {
+                    options.post.chromatic_aberration = Some(parse_number(&value()?)?)
+                }
+                "--vignette" => options.post.vignette = Some(parse_number(&value()?)?),
+                "--grain" => options.post.grain = Some(parse_number(&value()?)?),
+                "--lut" => options.lut = Some(PathBuf::from(value()?)),
+                "--exposure-comp" => options.display.exposure = parse_number(&value()?)?,
+                "--tonemap" => {
+                    options.display.tone_map = match value()?.as_str() {
+                        "clip" => ToneMap::Clip,
+                        "reinhard" => ToneMap::Reinhard,
+                        "hable" => ToneMap::Hable,
+                        "aces" => ToneMap::Aces,
+                        other => return Err(format!("unknown tone map '{other}'")),
+                    }
+                }
+                "--hdr" => options.hdr = Some(PathBuf::from(value()?)),
+                "--time-limit" => {
+                    let seconds: f64 = parse_number(&value()?)?;
+                    if !(seconds > 0.0 && seconds.is_finite()) {
+                        return Err(format!("invalid time limit '{seconds}'"));
+                    }
+                    options.time_limit = Some(Duration::from_secs_f64(seconds));
+                }
+                _ => return Err(format!("unknown argument '{arg}'")),
+            }
+        }
+
+        let layout = match stereo.as_deref() {
+            None => None,
+            Some("side-by-side") => Some(StereoLayout::SideBySide),
+            Some("top-bottom") => Some(StereoLayout::TopBottom),
+            Some("ods") => {
+                if !matches!(projection.as_str(), "perspective" | "equirectangular") {
+                    return Err("--stereo ods is an equirectangular panorama".to_string());
+                }
+                if convergence.is_some() {
+                    return Err("--convergence does not apply to --stereo ods".to_string());
+                }
+                projection = "equirectangular".to_string();
+                Some(StereoLayout::TopBottom)
+            }
+            Some(other) => return Err(format!("unknown stereo layout '{other}'")),
+        };
+        match layout {
+            Some(layout) => {
+                let mut settings = Stereo {
+                    layout,
+                    convergence,
+                    ..Stereo::default()
+                };
+                settings.interocular = interocular.unwrap_or(settings.interocular);
+                if !settings.interocular.is_finite()
+                    || settings.interocular < 0.0
+                    || convergence.is_some_and(|c: f64| c.is_nan() || c <= 0.0)
+                {
+                    return Err("invalid stereo settings".to_string());
+                }
+                options.stereo = Some(settings);
+            }
+            None if interocular.is_some() || convergence.is_some() => {
+                return Err("--interocular and --convergence require --stereo".to_string());
+            }
+            None => {}
+        }
+
+        options.projection = match projection.as_str() {
+            "perspective" => Projection::Perspective,
+            "orthographic" => Projection::Orthographic {
+                height: ortho_height,
+            },
+            "fisheye" => Projection::Fisheye {
+                mapping: FisheyeMapping::Equidistant,
+                fov,
+            },
+            "fisheye-equisolid" => Projection::Fisheye {
+                mapping: FisheyeMapping::Equisolid,
+                fov,
+            },
+            "equirectangular" => Projection::Equirectangular,
+            other => return Err(format!("unknown projection '{other}'")),
+        };
+
+        if let Some(camera) = &options.physical {
+            let settings = [
+                camera.focal_length,
+                camera.sensor_width,
+                camera.sensor_height,
+                camera.f_number,
+                camera.focus_distance,
+                camera.shutter,
+                camera.iso,
+            ];
+            if settings.iter().any(|v| !(*v > 0.0 && v.is_finite())) {
+                return Err("camera settings must be positive".to_string());
+            }
+        }
+
+        if focus_distance && options.focus.is_some() {
+            return Err("--focus and --focus-distance are mutually exclusive".to_string());
+        }
+        if options.aperture_blades.is_some_and(|blades| blades < 3) {
+            return Err("an aperture needs at least 3 blades".to_string());
+        }
+        if options.aperture_blades.is_some() && options.aperture_mask.is_some() {
+            return Err("--aperture-blades and --aperture-mask are mutually exclusive".to_string());
+        }
+        if options.lens.is_some() && !matches!(options.projection, Projection::Perspective) {
+            return Err("--lens requires the perspective projection".to_string());
+        }
+        if options.lens.is_some() && options.stereo.is_some_and(|s| s.convergence.is_some()) {
+            return Err("--convergence does not apply to a traced --lens".to_string());
+        }
+
+        if options.tile_size == 0 {
+            return Err("--tile-size must be positive".to_string());
+        }
+        // A narrower filter would miss the samples of some pixels entirely
+        if options
+            .filter_radius
+            .is_some_and(|r| !r.is_finite() || r < 0.5)
+        {
+            return Err("--filter-radius must be at least 0.5".to_string());
+        }
+        if [options.clamp, options.firefly_factor]
+            .iter()
+            .flatten()
+            .any(|v| v.is_nan() || *v <= 0.0)
+        {
+            return Err("--clamp and --firefly-filter must be positive".to_string());
+        }
+        if options.samples_per_pixel <= 0 || options.pass_samples == Some(0) {
+            return Err("--spp and --progressive must be positive".to_string());
+        }
+        if options.checkpoint.is_some() && options.pass_samples.is_none() {
+            return Err("--checkpoint requires --progressive".to_string());
+        }
+        if options.resume && options.checkpoint.is_none() {
+            return Err("--resume requires --checkpoint".to_string());
+        }
+        if options
+            .adaptive_threshold
+            .is_some_and(|t| !t.is_finite() || t <= 0.0)
+        {
+            return Err("--adaptive must be positive".to_string());
+        }
+        // Fewer samples give no error estimate to adapt to
+        if options.min_samples < 2 {
+            return Err("--min-spp must be at least 2".to_string());
+        }
+        if options
+            .max_samples
+            .is_some_and(|max| max < options.min_samples)
+        {
+            return Err("--max-spp must be at least --min-spp".to_string());
+        }
+        let modes = [
+            options.pass_samples.is_some(),
+            options.adaptive_threshold.is_some(),
+            options.time_limit.is_some(),
+        ];
+        if modes.iter().filter(|m| **m).count() > 1 {
+            return Err(
+                "--progressive, --adaptive and --time-limit are mutually exclusive".to_string(),
+            );
+        }
+
+        let post = &options.post;
+        if post
+            .bloom
+            .is_some_and(|b| !(b.threshold >= 0.0 && b.strength >= 0.0 && b.radius > 0.0))
+        {
+            return Err("bloom settings must be positive".to_string());
+        }
+        if post.glare.is_some_and(|g| {
+            !(g.threshold >= 0.0 && g.strength >= 0.0 && g.streaks > 0 && g.length > 0.0)
+        }) {
+            return Err("glare settings must be positive".to_string());
+        }
+        if post
+            .chromatic_aberration
+            .is_some_and(|amount| amount.is_nan() || amount.abs() >= 0.5)
+        {
+            return Err("chromatic aberration must be below 0.5".to_string());
+        }
+        if [post.vignette, post.grain]
+            .iter()
+            .flatten()
+            .any(|v| v.is_nan() || *v < 0.0)
+        {
+            return Err("vignette and grain must not be negative".to_string());
+        }
+
+        if !options.display.exposure.is_finite() {
+            return Err("--exposure-comp must be a finite number of stops".to_string());
+        }
+
+        // The rendered image is in the working space
+        options.display.color_space = options.color_space;
+        Ok(options)
+    }
+}
+
+/// Command line settings for `raytracer compare`.
+pub struct CompareOptions {
+    pub reference: PathBuf,
+    pub test: PathBuf,
+    // Where to write the FLIP error as a false color image
+    pub diff: Option<PathBuf>,
+}
+
+impl CompareOptions {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<CompareOptions, String> {
+        let mut images = Vec::new();
+        let mut diff = None;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--diff" => {
+                    let path = args.next().ok_or("missing value for --diff")?;
+                    diff = Some(PathBuf::from(path));
+                }
+                _ if arg.starts_with("--") => return Err(format!("unknown argument '{arg}'")),
+                _ => images.push(PathBuf::from(arg)),
+            }
+        }
+
+        let [reference, test] = <[PathBuf; 2]>::try_from(images)
+            .map_err(|_| "compare needs a reference and a test image".to_string())?;
+        Ok(CompareOptions {
+            reference,
+            test,
+            diff,
+        })
+    }
+}
+
+fn physical(options: &mut Options) -> &mut PhysicalCamera {
+    options.physical.get_or_insert_with(PhysicalCamera::default)
+}
+
+fn parse_focus(value: &str) -> Result<Focus, String> {
+    if value == "look-at" {
+        return Ok(Focus::LookAt);
+    }
+    match value.split_once(',') {
+        Some((x, y)) => Ok(Focus::Pixel(parse_number(x)?, parse_number(y)?)),
+        None => Ok(Focus::Object(value.to_string())),
+    }
+}
+
+fn parse_bloom(value: &str) -> Result<Bloom, String> {
+    let mut bloom = Bloom::default();
+    let mut parts = value.split(',');
+    bloom.threshold = parse_number(parts.next().unwrap_or_default())?;
+    if let Some(strength) = parts.next() {
+        bloom.strength = parse_number(strength)?;
+    }
+    if let Some(radius) = parts.next() {
+        bloom.radius = parse_number(radius)?;
+    }
+    if parts.next().is_some() {
+        return Err(format!("too many bloom settings in '{value}'"));
+    }
+    Ok(bloom)
+}
+
+fn parse_glare(value: &str) -> Result<Glare, String> {
+    let mut glare = Glare::default();
+    let mut parts = value.split(',');
+    glare.threshold = parse_number(parts.next().unwrap_or_default())?;
+    if let Some(strength) = parts.next() {
+        glare.strength = parse_number(strength)?;
+    }
+    if let Some(streaks) = parts.next() {
+        glare.streaks = parse_number(streaks)?;
+    }
+    if let Some(length) = parts.next() {
+        glare.length = parse_number(length)?;
+    }
+    if parts.next().is_some() {
+        return Err(format!("too many glare settings in '{value}'"));
+    }
+    Ok(glare)
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid number '{value}'"))
+}
@@ -0,0 +1,526 @@
+use crate::color::{self, Color};
+use crate::common::{self, Pcg32};
+use crate::image::Image;
+use crate::stereo::{Eye, Stereo};
+use crate::tile::Tile;
+
+/// Glow spreading from highlights, like light scattered in the lens.
+#[derive(Clone, Copy)]
+pub struct Bloom {
+    // Luminance above which pixels glow
+    pub threshold: f64,
+    // Fraction of the light above the threshold that is spread out
+    pub strength: f64,
+    // Standard deviation of the glow as a fraction of the image width
+    pub radius: f64,
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Bloom {
+            threshold: 1.0,
+            strength: 0.1,
+            radius: 0.01,
+        }
+    }
+}
+
+/// Star-shaped streaks from highlights, like diffraction at the aperture
+/// blades.
+#[derive(Clone, Copy)]
+pub struct Glare {
+    // Luminance above which pixels streak
+    pub threshold: f64,
+    // Fraction of the light above the threshold that goes into the streaks
+    pub strength: f64,
+    // Number of rays in the star, evenly spaced starting horizontally
+    pub streaks: u32,
+    // Distance over which a streak fades to 1/e, as a fraction of the
+    // image width
+    pub length: f64,
+}
+
+impl Default for Glare {
+    fn default() -> Self {
+        Glare {
+            threshold: 1.0,
+            strength: 0.05,
+            streaks: 4,
+            length: 0.05,
+        }
+    }
+}
+
+/// Effects applied to the linear image before it is encoded for display.
+/// Each is off when None.
+#[derive(Clone, Copy, Default)]
+pub struct PostEffects {
+    pub bloom: Option<Bloom>,
+    pub glare: Option<Glare>,
+    // How much larger the red image is than the blue one, relative to the
+    // image size
+    pub chromatic_aberration: Option<f64>,
+    // Darkening towards the corners; see `vignette`
+    pub vignette: Option<f64>,
+    // Standard deviation of the film grain relative to the pixel value
+    pub grain: Option<f64>,
+}
+
+impl PostEffects {
+    /// Runs the enabled effects in the order light meets them: scattering
+    /// and aberration in the lens, falloff towards the edge of the frame,
+    /// then the grain of the film. `seed` keys the grain pattern. The lens
+    /// effects of a stereo pair are applied to each eye's image on its own,
+    /// so that they center on it and don't bleed across the seam.
+    pub fn apply(&self, image: &Image, seed: u64, stereo: Option<&Stereo>) -> Image {
+        let mut image = per_eye(image, stereo, |part, _| self.lens(part));
+        if let Some(amount) = self.grain {
+            image = grain(&image, amount, seed);
+        }
+        image
+    }
+
+    fn lens(&self, image: &Image) -> Image {
+        let mut image = image.clone();
+        if let Some(settings) = &self.bloom {
+            image = bloom(&image, settings);
+        }
+        if let Some(settings) = &self.glare {
+            image = glare(&image, settings);
+        }
+        if let Some(amount) = self.chromatic_aberration {
+            image = chromatic_aberration(&image, amount);
+        }
+        if let Some(strength) = self.vignette {
+            image = vignette(&image, strength);
+        }
+        image
+    }
+}
+
+/// Adds a Gaussian blur of the light above the threshold.
+pub fn bloom(image: &Image, settings: &Bloom) -> Image {
+    let bright = bright_pass(image, settings.threshold);
+    let sigma = (settings.radius * image.width() as f64).max(0.5);
+    let glow = gaussian_blur(&bright, sigma);
+    add_scaled(image, &glow, settings.strength)
+}
+
+/// Adds streaks of the light above the threshold. Each streak falls off
+/// exponentially; it is built in passes of four taps, each pass reaching
+/// four times as far as the one before.
+pub fn glare(image: &Image, settings: &Glare) -> Image {
+    let bright = bright_pass(image, settings.threshold);
+    let length = (settings.length * image.width() as f64).max(1.0);
+    let decay = f64::exp(-1.0 / length);
+    // Enough passes to reach five lengths
+    let passes = ((5.0 * length).log(4.0).ceil() as i32).max(1);
+
+    let mut result = image.clone();
+    for k in 0..settings.streaks {
+        let angle = 2.0 * common::PI * k as f64 / settings.streaks as f64;
+        let (dx, dy) = (angle.cos(), -angle.sin());
+        let mut streak = bright.clone();
+        let mut step = 1.0;
+        for _ in 0..passes {
+            let input = streak.clone();
+            for y in 0..image.height() {
+                for x in 0..image.width() {
+                    let (px, py) = (x as f64 + 0.5, y as f64 + 0.5);
+                    let mut sum = Color::default();
+                    for s in 0..4 {
+                        let d = step * s as f64;
+                        sum += decay.powf(d) * bilinear(&input, px - d * dx, py - d * dy);
+                    }
+                    streak.set_pixel(x, y, sum);
+                }
+            }
+            step *= 4.0;
+        }
+        // The passes add up to the kernel decay^i for i below 4^passes
+        let total = (1.0 - decay.powf(step)) / (1.0 - decay);
+        let scale = settings.strength / (settings.streaks as f64 * total);
+        result = add_scaled(&result, &streak, scale);
+    }
+    result
+}
+
+/// Lateral chromatic aberration: the red image is scaled up by `amount`
+/// about the center and the blue one down by as much, so colors fringe
+/// towards the edges.
+pub fn chromatic_aberration(image: &Image, amount: f64) -> Image {
+    let (width, height) = (image.width() as f64, image.height() as f64);
+    let (cx, cy) = (width / 2.0, height / 2.0);
+    let mut result = Image::new(image.width(), image.height());
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            let (px, py) = (x as f64 + 0.5 - cx, y as f64 + 0.5 - cy);
+            let channel = |scale: f64| {
+                let sx = (cx + px / scale).clamp(0.5, width - 0.5);
+                let sy = (cy + py / scale).clamp(0.5, height - 0.5);
+                bilinear(image, sx, sy)
+            };
+            let red = channel(1.0 + amount).x();
+            let blue = channel(1.0 - amount).z();
+            result.set_pixel(x, y, Color::new(red, image.pixel(x, y).y(), blue));
+        }
+    }
+    result
+}
+
+/// Natural vignetting by the cos^4 law: a pixel at relative distance r from
+/// the center, with r = 1 in the corners, is dimmed to 1 / (1 + s r^2)^2.
+pub fn vignette(image: &Image, strength: f64) -> Image {
+    let (cx, cy) = (image.width() as f64 / 2.0, image.height() as f64 / 2.0);
+    let mut result = image.clone();
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            let (dx, dy) = (x as f64 + 0.5 - cx, y as f64 + 0.5 - cy);
+            let r2 = (dx * dx + dy * dy) / (cx * cx + cy * cy);
+            let falloff = 1.0 / (1.0 + strength * r2).powi(2);
+            result.set_pixel(x, y, falloff * image.pixel(x, y));
+        }
+    }
+    result
+}
+
+/// Monochrome film grain: every pixel is scaled by 1 + amount * n, with n
+/// drawn from a standard normal distribution.
+pub fn grain(image: &Image, amount: f64, seed: u64) -> Image {
+    // Own stream, so the pattern does not depend on anything rendered
+    const GRAIN_STREAM: u64 = 0x0067_7261_696e;
+    let mut rng = Pcg32::new(seed, GRAIN_STREAM);
+    let mut result = image.clone();
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            // Box-Muller
+            let (u1, u2) = (rng.next_f64(), rng.next_f64());
+            let n = f64::sqrt(-2.0 * (1.0 - u1).ln()) * f64::cos(2.0 * common::PI * u2);
+            let scale = (1.0 + amount * n).max(0.0);
+            result.set_pixel(x, y, scale * image.pixel(x, y));
+        }
+    }
+    result
+}
+
+// The part of each pixel above `threshold` in luminance, keeping its hue
+fn bright_pass(image: &Image, threshold: f64) -> Image {
+    let mut result = Image::new(image.width(), image.height());
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            let pixel = image.pixel(x, y);
+            let luminance = color::luminance(pixel);
+            if luminance > threshold {
+                result.set_pixel(x, y, (luminance - threshold) / luminance * pixel);
+            }
+        }
+    }
+    result
+}
+
+// Separable Gaussian blur; weights falling outside the image are left out
+fn gaussian_blur(image: &Image, sigma: f64) -> Image {
+    let radius = (3.0 * sigma).ceil() as i64;
+    let weights: Vec<f64> = (-radius..=radius)
+        .map(|i| f64::exp(-((i * i) as f64) / (2.0 * sigma * sigma)))
+        .collect();
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let pass = |input: &Image, horizontal: bool| {
+        let mut output = Image::new(input.width(), input.height());
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = Color::default();
+                let mut weight_sum = 0.0;
+                for (i, w) in (-radius..=radius).zip(&weights) {
+                    let (nx, ny) = if horizontal { (x + i, y) } else { (x, y + i) };
+                    if nx < 0 || ny < 0 || nx >= width || ny >= height {
+                        continue;
+                    }
+                    sum += *w * input.pixel(nx as usize, ny as usize);
+                    weight_sum += w;
+                }
+                output.set_pixel(x as usize, y as usize, sum / weight_sum);
+            }
+        }
+        output
+    };
+    pass(&pass(image, true), false)
+}
+
+// Bilinear lookup at (x, y) in pixel units, pixel centers at half
+// integers. Outside the image counts as black.
+fn bilinear(image: &Image, x: f64, y: f64) -> Color {
+    let (x, y) = (x - 0.5, y - 0.5);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let texel = |i: f64, j: f64| {
+        if i < 0.0 || j < 0.0 || i >= image.width() as f64 || j >= image.height() as f64 {
+            Color::default()
+        } else {
+            image.pixel(i as usize, j as usize)
+        }
+    };
+    (1.0 - fy) * ((1.0 - fx) * texel(x0, y0) + fx * texel(x0 + 1.0, y0))
+        + fy * ((1.0 - fx) * texel(x0, y0 + 1.0) + fx * texel(x0 + 1.0, y0 + 1.0))
+}
+
+fn add_scaled(image: &Image, other: &Image, scale: f64) -> Image {
+    let mut result = image.clone();
+    for y in 0..image.height() {
+        for x in 0..image.width() {
+            result.set_pixel(x, y, image.pixel(x, y) + scale * other.pixel(x, y));
+        }
+    }
+    result
+}
+
+/// Replaces isolated bright pixels with the average of their neighbours. A
+/// pixel counts as a firefly when its luminance is more than `factor` times
+/// that of its brightest neighbour, so highlights that span several pixels
+/// are kept. This is biased: real energy in those pixels is thrown away.
+pub fn remove_fireflies(image: &Image, factor: f64) -> Image {
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let mut result = image.clone();
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut brightest: f64 = 0.0;
+            let mut sum = Color::default();
+            let mut count = 0;
+            for ny in y - 1..=y + 1 {
+                for nx in x - 1..=x + 1 {
+                    if (nx, ny) == (x, y) || nx < 0 || ny < 0 || nx >= width || ny >= height {
+                        continue;
+                    }
+                    let neighbour = image.pixel(nx as usize, ny as usize);
+                    brightest = brightest.max(color::luminance(neighbour));
+                    sum += neighbour;
+                    count += 1;
+                }
+            }
+
+            let pixel = image.pixel(x as usize, y as usize);
+            if count > 0 && color::luminance(pixel) > factor * brightest.max(1.0e-3) {
+                result.set_pixel(x as usize, y as usize, sum / count as f64);
+            }
+        }
+    }
+    result
+}
+
+/// Runs `effect` on each eye's part of a stereo pair on its own, so that
+/// it can't reach across the seam, or on the whole image without a pair.
+/// `effect` is also told where the part lies in `image`.
+pub fn per_eye(
+    image: &Image,
+    stereo: Option<&Stereo>,
+    effect: impl Fn(&Image, &Tile) -> Image,
+) -> Image {
+    let Some(stereo) = stereo else {
+        let whole = Tile {
+            x0: 0,
+            y0: 0,
+            x1: image.width(),
+            y1: image.height(),
+        };
+        return effect(image, &whole);
+    };
+
+    let (width, height) = stereo.eye_size(image.width(), image.height());
+    let mut result = image.clone();
+    for eye in [Eye::Left, Eye::Right] {
+        let region = stereo.region(eye, width, height);
+        paste(
+            &mut result,
+            &effect(&crop(image, &region), &region),
+            &region,
+        );
+    }
+    result
+}
+
+pub fn crop(image: &Image, region: &Tile) -> Image {
+    let mut result = Image::new(region.x1 - region.x0, region.y1 - region.y0);
+    for y in region.y0..region.y1 {
+        for x in region.x0..region.x1 {
+            result.set_pixel(x - region.x0, y - region.y0, image.pixel(x, y));
+        }
+    }
+    result
+}
+
+fn paste(image: &mut Image, part: &Image, region: &Tile) {
+    for y in region.y0..region.y1 {
+        for x in region.x0..region.x1 {
+            image.set_pixel(x, y, part.pixel(x - region.x0, y - region.y0));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flat(width: usize, height: usize, value: f64) -> Image {
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                image.set_pixel(x, y, Color::new(value, value, value));
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn isolated_pixel_is_replaced() {
+        let mut image = flat(5, 5, 0.2);
+        image.set_pixel(2, 2, Color::new(50.0, 50.0, 50.0));
+
+        let result = remove_fireflies(&image, 4.0);
+        assert!((result.pixel(2, 2).x() - 0.2).abs() < 1.0e-12);
+        assert!((result.pixel(1, 1).x() - 0.2).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn wide_highlight_is_kept() {
+        let mut image = flat(5, 5, 0.2);
+        image.set_pixel(2, 2, Color::new(50.0, 50.0, 50.0));
+        image.set_pixel(3, 2, Color::new(40.0, 40.0, 40.0));
+
+        let result = remove_fireflies(&image, 4.0);
+        assert_eq!(result.pixel(2, 2).x(), 50.0);
+        assert_eq!(result.pixel(3, 2).x(), 40.0);
+    }
+
+    fn total(image: &Image) -> f64 {
+        (0..image.height())
+            .flat_map(|y| (0..image.width()).map(move |x| (x, y)))
+            .map(|(x, y)| image.pixel(x, y).x())
+            .sum()
+    }
+
+    #[test]
+    fn bloom_spreads_only_light_above_the_threshold() {
+        let dim = flat(21, 21, 0.5);
+        assert_eq!(total(&bloom(&dim, &Bloom::default())), total(&dim));
+
+        let mut image = flat(41, 41, 0.0);
+        image.set_pixel(20, 20, Color::new(11.0, 11.0, 11.0));
+        let settings = Bloom {
+            threshold: 1.0,
+            strength: 0.5,
+            radius: 0.05,
+        };
+        let result = bloom(&image, &settings);
+        assert!(result.pixel(22, 20).x() > 0.0);
+        assert!(result.pixel(22, 20).x() < result.pixel(21, 20).x());
+        // Half of the 10 units above the threshold, away from the edges
+        assert!((total(&result) - total(&image) - 5.0).abs() < 1.0e-3);
+    }
+
+    #[test]
+    fn glare_streaks_along_the_star() {
+        let mut image = flat(41, 41, 0.0);
+        image.set_pixel(20, 20, Color::new(101.0, 101.0, 101.0));
+        let settings = Glare {
+            threshold: 1.0,
+            strength: 1.0,
+            streaks: 4,
+            length: 0.1,
+        };
+        let result = glare(&image, &settings);
+        for (x, y) in [(25, 20), (15, 20), (20, 25), (20, 15)] {
+            assert!(result.pixel(x, y).x() > 0.1);
+        }
+        assert!(result.pixel(25, 25).x().abs() < 1.0e-9);
+        assert!(result.pixel(30, 20).x() < result.pixel(25, 20).x());
+    }
+
+    #[test]
+    fn chromatic_aberration_fringes_towards_the_edges() {
+        let mut image = flat(21, 21, 0.0);
+        for y in 0..21 {
+            for x in 17..21 {
+                image.set_pixel(x, y, Color::new(1.0, 1.0, 1.0));
+            }
+        }
+        let result = chromatic_aberration(&image, 0.1);
+        // Red spreads outwards from the center, blue inwards
+        assert!(result.pixel(17, 10).x() < 1.0);
+        assert_eq!(result.pixel(17, 10).y(), 1.0);
+        assert!(result.pixel(16, 10).z() > 0.0);
+        assert_eq!(result.pixel(16, 10).x(), 0.0);
+
+        let uniform = flat(9, 9, 0.3);
+        let result = chromatic_aberration(&uniform, 0.1);
+        assert!((result.pixel(0, 0).x() - 0.3).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn vignette_follows_the_cos4_law() {
+        let result = vignette(&flat(11, 11, 1.0), 1.0);
+        assert_eq!(result.pixel(5, 5).x(), 1.0);
+        // The corner pixel's center is at r^2 = (5 / 5.5)^2
+        let r2 = (5.0f64 / 5.5).powi(2);
+        assert!((result.pixel(0, 0).x() - 1.0 / (1.0 + r2).powi(2)).abs() < 1.0e-12);
+        assert!(result.pixel(0, 5).x() > result.pixel(0, 0).x());
+    }
+
+    #[test]
+    fn grain_is_repeatable_and_keeps_the_mean() {
+        let image = flat(64, 64, 0.5);
+        let a = grain(&image, 0.1, 7);
+        let b = grain(&image, 0.1, 7);
+        assert_eq!(a.pixel(3, 4).x(), b.pixel(3, 4).x());
+        assert_ne!(a.pixel(3, 4).x(), grain(&image, 0.1, 8).pixel(3, 4).x());
+
+        let mean = total(&a) / (64.0 * 64.0);
+        assert!((mean - 0.5).abs() < 0.005);
+        // Monochrome: all channels move together
+        assert_eq!(a.pixel(5, 5).x(), a.pixel(5, 5).z());
+    }
+
+    #[test]
+    fn stereo_eyes_are_processed_separately() {
+        let stereo = Stereo::default();
+        let effects = PostEffects {
+            vignette: Some(1.0),
+            ..Default::default()
+        };
+        let result = effects.apply(&flat(22, 11, 1.0), 0, Some(&stereo));
+        // Each eye is bright in its own middle and dark at the seam
+        for x in [5, 16] {
+            assert_eq!(result.pixel(x, 5).x(), 1.0);
+        }
+        assert!(result.pixel(10, 5).x() < 0.9);
+        for x in 0..11 {
+            assert_eq!(result.pixel(x, 3).x(), result.pixel(x + 11, 3).x());
+        }
+    }
+
+    #[test]
+    fn per_eye_hands_each_eye_its_own_part() {
+        let stereo = Stereo::default();
+        let mut image = flat(22, 11, 0.0);
+        for y in 0..11 {
+            for x in 11..22 {
+                image.set_pixel(x, y, Color::new(1.0, 1.0, 1.0));
+            }
+        }
+        // Spreads the mean over the part, which would mix the eyes if it
+        // saw both
+        let result = per_eye(&image, Some(&stereo), |part, region| {
+            assert_eq!((region.x1 - region.x0, region.y1 - region.y0), (11, 11));
+            let sum: f64 = (0..11)
+                .flat_map(|y| (0..11).map(move |x| (x, y)))
+                .map(|(x, y)| part.pixel(x, y).x())
+                .sum();
+            flat(11, 11, sum / 121.0)
+        });
+        for y in 0..11 {
+            for x in 0..22 {
+                assert_eq!(result.pixel(x, y).x(), image.pixel(x, y).x());
+            }
+        }
+    }
+}
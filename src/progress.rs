@@ -0,0 +1,68 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// Snapshot of a render in flight, handed to the progress callback.
+pub struct Progress {
+    pub tiles_done: usize,
+    pub tiles_total: usize,
+    pub elapsed: Duration,
+    pub eta: Duration,
+    pub rays_per_sec: f64,
+}
+
+/// Shared flag that stops a render at the next tile boundary. Tiles that
+/// already finished are kept, so the render returns a partial image.
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+pub(crate) struct ProgressTracker {
+    start: Instant,
+    tiles_total: usize,
+    tiles_done: AtomicUsize,
+    rays: AtomicU64,
+}
+
+impl ProgressTracker {
+    pub fn new(tiles_total: usize) -> Self {
+        ProgressTracker {
+            start: Instant::now(),
+            tiles_total,
+            tiles_done: AtomicUsize::new(0),
+            rays: AtomicU64::new(0),
+        }
+    }
+
+    pub fn tile_done(&self, rays: u64) -> Progress {
+        let tiles_done = self.tiles_done.fetch_add(1, Ordering::Relaxed) + 1;
+        let rays = self.rays.fetch_add(rays, Ordering::Relaxed) + rays;
+        let elapsed = self.start.elapsed();
+
+        let remaining = self.tiles_total.saturating_sub(tiles_done);
+        let eta = elapsed.mul_f64(remaining as f64 / tiles_done as f64);
+
+        Progress {
+            tiles_done,
+            tiles_total: self.tiles_total,
+            elapsed,
+            eta,
+            rays_per_sec: rays as f64 / elapsed.as_secs_f64().max(1.0e-9),
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use crate::common;
+use crate::vec3::{Point3, Vec3};
+
+/// How positions on the film map to camera rays. Every projection but
+/// perspective is a pinhole model without depth of field.
+#[derive(Clone, Copy, Default)]
+pub enum Projection {
+    // Thin lens with the camera's field of view and defocus settings
+    #[default]
+    Perspective,
+    // Parallel rays along the view direction. `height` is the height of the
+    // view in world units; None matches the perspective view at the focus
+    // distance.
+    Orthographic {
+        height: Option<f64>,
+    },
+    // Circular fisheye whose image circle fits the film height and spans
+    // `fov` degrees.
+    Fisheye {
+        mapping: FisheyeMapping,
+        fov: f64,
+    },
+    // Full 360x180 degree panorama; the film should be twice as wide as tall
+    Equirectangular,
+}
+
+#[derive(Clone, Copy)]
+pub enum FisheyeMapping {
+    // Distance from the image center is proportional to the angle
+    Equidistant,
+    // Equal solid angles cover equal areas of the film
+    Equisolid,
+}
+
+impl Projection {
+    /// Camera-space origin and direction for the film position (x, y), with
+    /// both coordinates in [-1, 1] from the bottom left to the top right of
+    /// a film `aspect` times wider than tall. Camera space looks down -z
+    /// with +y up. `view_height` is the orthographic height to use when none
+    /// is set. Returns None outside the image circle of a fisheye.
+    /// Perspective rays depend on the lens and are made by the camera.
+    pub fn camera_ray(
+        &self,
+        x: f64,
+        y: f64,
+        aspect: f64,
+        view_height: f64,
+    ) -> Option<(Point3, Vec3)> {
+        match *self {
+            Projection::Perspective => None,
+            Projection::Orthographic { height } => {
+                let half_height = height.unwrap_or(view_height) / 2.0;
+                Some((
+                    Point3::new(x * aspect * half_height, y * half_height, 0.0),
+                    Vec3::new(0.0, 0.0, -1.0),
+                ))
+            }
+            Projection::Fisheye { mapping, fov } => {
+                let (px, py) = (x * aspect, y);
+                let r = f64::sqrt(px * px + py * py);
+                let max_theta = common::degress_to_radians(fov) / 2.0;
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => r * max_theta,
+                    FisheyeMapping::Equisolid => {
+                        let s = r * f64::sin(max_theta / 2.0);
+                        if s > 1.0 {
+                            return None;
+                        }
+                        2.0 * s.asin()
+                    }
+                };
+                if r > 1.0 || theta > common::PI {
+                    return None;
+                }
+
+                let phi = py.atan2(px);
+                Some((
+                    Point3::default(),
+                    Vec3::new(
+                        theta.sin() * phi.cos(),
+                        theta.sin() * phi.sin(),
+                        -theta.cos(),
+                    ),
+                ))
+            }
+            Projection::Equirectangular => {
+                let longitude = x * common::PI;
+                let latitude = y * common::PI / 2.0;
+                Some((
+                    Point3::default(),
+                    Vec3::new(
+                        latitude.cos() * longitude.sin(),
+                        latitude.sin(),
+                        -latitude.cos() * longitude.cos(),
+                    ),
+                ))
+            }
+        }
+    }
+
+    /// Camera-space direction from the left to the right eye of a stereo
+    /// pair at the film position x. Panoramas turn it with the view
+    /// direction, giving omni-directional stereo: every column of the
+    /// panorama is seen in stereo, as if the viewer turned their head.
+    pub fn stereo_baseline(&self, x: f64) -> Vec3 {
+        match self {
+            Projection::Equirectangular => {
+                let longitude = x * common::PI;
+                Vec3::new(longitude.cos(), 0.0, longitude.sin())
+            }
+            _ => Vec3::new(1.0, 0.0, 0.0),
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::sync::Arc;
+
+use crate::hittable::{AlphaMask, HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::{self, Point3, Vec3};
+
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    mat: Arc<dyn Material>,
+    normal: Vec3,
+    d: f64,
+    alpha_mask: Option<Arc<AlphaMask>>,
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, mat: Arc<dyn Material>) -> Self {
+        let n = vec3::cross(u, v);
+        let normal = vec3::unit_vector(n);
+        let d = vec3::dot(normal, q);
+        let w = n / vec3::dot(n, n);
+
+        Quad {
+            q,
+            u,
+            v,
+            w,
+            mat,
+            normal,
+            d,
+            alpha_mask: None,
+        }
+    }
+
+    pub fn with_alpha_mask(mut self, mask: Arc<AlphaMask>) -> Self {
+        self.alpha_mask = Some(mask);
+        self
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        let denom = vec3::dot(self.normal, ray.direction());
+
+        // No hit if the ray is parallel to the plane
+        if denom.abs() < 1.0e-8 {
+            return false;
+        }
+
+        let t = (self.d - vec3::dot(self.normal, ray.origin())) / denom;
+        if t <= t_min || t >= t_max {
+            return false;
+        }
+
+        // Planar coordinates of the hit point relative to the quad's corner
+        let intersection = ray.at(t);
+        let planar_hitpt_vector = intersection - self.q;
+        let alpha = vec3::dot(self.w, vec3::cross(planar_hitpt_vector, self.v));
+        let beta = vec3::dot(self.w, vec3::cross(self.u, planar_hitpt_vector));
+
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return false;
+        }
+
+        if let Some(mask) = &self.alpha_mask
+            && !mask.is_opaque(alpha, beta, &intersection)
+        {
+            return false;
+        }
+
+        rec.t = t;
+        rec.p = intersection;
+        rec.u = alpha;
+        rec.v = beta;
+        rec.set_face_normal(ray, self.normal);
+        rec.set_tangent_frame(self.normal, self.u);
+        rec.mat = Some(self.mat.clone());
+        true
+    }
+
+    fn materials(&self) -> Vec<&Arc<dyn Material>> {
+        vec![&self.mat]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable_list::HittableList;
+    use crate::image::Image;
+    use crate::material::Lambertian;
+    use crate::texture::ImageTexture;
+
+    /// Mask whose left and right halves in u have the given opacities.
+    fn mask(left: f64, right: f64) -> Arc<AlphaMask> {
+        let mut image = Image::new(2, 1);
+        image.set_pixel(0, 0, Color::new(left, left, left));
+        image.set_pixel(1, 0, Color::new(right, right, right));
+        Arc::new(AlphaMask::new(Arc::new(ImageTexture::new(image)), 0.5))
+    }
+
+    /// `object` in front of a wall at z = -5, and the ID and hit record of
+    /// what a ray from (x, y, 5) along -z meets first.
+    fn first_hit(object: Box<dyn Hittable>, x: f64, y: f64) -> (usize, HitRecord) {
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut world = HittableList::new();
+        world.add(object);
+        world.add(Box::new(Quad::new(
+            Point3::new(-10.0, -10.0, -5.0),
+            Vec3::new(20.0, 0.0, 0.0),
+            Vec3::new(0.0, 20.0, 0.0),
+            material,
+        )));
+        let ray = Ray::new(Point3::new(x, y, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::new();
+        assert!(world.hit(&ray, 0.001, f64::INFINITY, &mut rec));
+        (rec.object_id, rec)
+    }
+
+    #[test]
+    fn masked_hits_fall_through() {
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let quad = |mask| {
+            Box::new(
+                Quad::new(
+                    Point3::new(-1.0, -1.0, 0.0),
+                    Vec3::new(2.0, 0.0, 0.0),
+                    Vec3::new(0.0, 2.0, 0.0),
+                    material.clone(),
+                )
+                .with_alpha_mask(mask),
+            )
+        };
+
+        assert_eq!(first_hit(quad(mask(0.0, 0.0)), 0.5, 0.0).0, 1);
+        assert_eq!(first_hit(quad(mask(0.0, 1.0)), -0.5, 0.0).0, 1);
+        let (id, rec) = first_hit(quad(mask(0.0, 1.0)), 0.5, 0.0);
+        assert_eq!(id, 0);
+        assert!((rec.t - 5.0).abs() < 1.0e-9);
+    }
+}
@@ -0,0 +1,256 @@
+//! Renders small canonical scenes and compares them with the references in
+//! `tests/references`, so that changes to materials and geometry can't
+//! silently change what gets rendered. After an intended change, re-render
+//! the references with
+//!
+//!     UPDATE_REFERENCES=1 cargo test --release regression
+//!
+//! and check the new images in along with it. Scenes that load image or
+//! IES files read them from `tests/fixtures`.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use crate::camera::Camera;
+use crate::color;
+use crate::common;
+use crate::compare::Metrics;
+use crate::image::Image;
+use crate::progress::CancelToken;
+use crate::sampler::SamplerKind;
+use crate::scene::SceneKind;
+
+const WIDTH: i32 = 48;
+const SAMPLES: i32 = 64;
+const SEED: u64 = 1;
+// References average many more samples, drawn from other random streams
+const REFERENCE_SAMPLES: i32 = 1024;
+const REFERENCE_SEED: u64 = 1000;
+// Keys the random numbers drawn while building scenes, such as noise textures
+const SCENE_SEED: u64 = 0;
+// Largest luminance of a sample, in both the references and the renders
+const CLAMP: f64 = 10.0;
+
+// Side in pixels of the square blocks whose means are compared
+const BLOCK: usize = 8;
+// Largest difference of a block mean, in standard errors
+const MAX_BLOCK_SCORE: f64 = 5.0;
+// Largest mean squared block score; about 1 when only the noise differs
+const MAX_MEAN_SCORE: f64 = 2.0;
+
+struct Case {
+    name: &'static str,
+    // Rendered from the view `--scene` gives it
+    scene: SceneKind,
+}
+
+impl Case {
+    fn render(&self, samples: i32, seed: u64, sampler: SamplerKind) -> Image {
+        common::seed_thread(SCENE_SEED);
+        let (scene, lens) = crate::build_scene(self.scene, &fixtures());
+        let mut camera = Camera::new(1.0, WIDTH, samples, &lens);
+        camera.seed = seed;
+        camera.sampler = sampler;
+        // Caustics otherwise throw fireflies too rare for the noise estimate
+        camera.clamp = Some(CLAMP);
+        camera
+            .render(&scene, &|_| {}, &CancelToken::new())
+            .resolve()
+    }
+
+    fn check(&self) {
+        let path = references().join(format!("{}.pfm", self.name));
+        if env::var_os("UPDATE_REFERENCES").is_some() {
+            let reference =
+                self.render(REFERENCE_SAMPLES, REFERENCE_SEED, SamplerKind::Independent);
+            fs::create_dir_all(references()).unwrap();
+            let file = File::create(&path).unwrap();
+            color::write_pfm(&mut BufWriter::new(file), &reference).unwrap();
+            return;
+        }
+
+        let reference = Image::read(&path).unwrap_or_else(|e| {
+            panic!(
+                "cannot read {}: {e}; render it with UPDATE_REFERENCES=1",
+                path.display()
+            )
+        });
+        // Two independent halves, whose difference measures the noise
+        let halves = [
+            self.render(SAMPLES / 2, SEED, SamplerKind::Independent),
+            self.render(SAMPLES / 2, SEED + 1, SamplerKind::Independent),
+        ];
+        if let Err(msg) = compare(&reference, &halves) {
+            // Keep the render around for `compare --diff`
+            let mut image = Image::new(reference.width(), reference.height());
+            for y in 0..image.height() {
+                for x in 0..image.width() {
+                    let c = 0.5 * (halves[0].pixel(x, y) + halves[1].pixel(x, y));
+                    image.set_pixel(x, y, c);
+                }
+            }
+            let failed = env::temp_dir().join(format!("{}.pfm", self.name));
+            File::create(&failed)
+                .and_then(|file| color::write_pfm(&mut BufWriter::new(file), &image))
+                .unwrap();
+            panic!(
+                "{}: {msg}; the render is in {}",
+                self.name,
+                failed.display()
+            );
+        }
+    }
+}
+
+fn references() -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "references"]
+        .iter()
+        .collect()
+}
+
+/// Image and IES files for the scenes that load them.
+fn fixtures() -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "fixtures"]
+        .iter()
+        .collect()
+}
+
+/// Checks that the average of two independent renders differs from
+/// `reference` by no more than their noise. The images are split into
+/// blocks, and the difference of each block's mean is scored against its
+/// standard error. Half the difference of the renders is a sample of that
+/// error at each pixel; the reference's share follows from its sample count.
+fn compare(reference: &Image, halves: &[Image; 2]) -> Result<(), String> {
+    let [a, b] = halves;
+    if (reference.width(), reference.height()) != (a.width(), a.height()) {
+        return Err(format!(
+            "the reference is {}x{} but the render is {}x{}",
+            reference.width(),
+            reference.height(),
+            a.width(),
+            a.height()
+        ));
+    }
+
+    let reference_share = SAMPLES as f64 / REFERENCE_SAMPLES as f64;
+    let mut scores = Vec::new();
+    for y0 in (0..a.height()).step_by(BLOCK) {
+        for x0 in (0..a.width()).step_by(BLOCK) {
+            let pixels: Vec<(usize, usize)> = (y0..(y0 + BLOCK).min(a.height()))
+                .flat_map(|y| (x0..(x0 + BLOCK).min(a.width())).map(move |x| (x, y)))
+                .collect();
+            let n = pixels.len() as f64;
+            for channel in 0..3 {
+                let value = |image: &Image, (x, y): (usize, usize)| {
+                    let c = image.pixel(x, y);
+                    [c.x(), c.y(), c.z()][channel]
+                };
+                let (mut difference, mut variance) = (0.0, 0.0);
+                for &p in &pixels {
+                    let (va, vb) = (value(a, p), value(b, p));
+                    difference += 0.5 * (va + vb) - value(reference, p);
+                    variance += 0.25 * (va - vb).powi(2);
+                }
+                let error = (variance * (1.0 + reference_share)).sqrt();
+                // A floor keeps noiseless blocks, e.g. of pure sky, from
+                // scoring float rounding
+                let score = difference / error.max(1.0e-4 * n);
+                if score.abs() > MAX_BLOCK_SCORE {
+                    return Err(format!(
+                        "block at {x0},{y0} is off by {score:.1} standard errors in channel \
+                         {channel} (mean difference {:.4})",
+                        difference / n
+                    ));
+                }
+                scores.push(score * score);
+            }
+        }
+    }
+
+    let mean_score = scores.iter().sum::<f64>() / scores.len() as f64;
+    if mean_score > MAX_MEAN_SCORE {
+        return Err(format!(
+            "blocks are off by {mean_score:.2} squared standard errors on average"
+        ));
+    }
+    Ok(())
+}
+
+const THREE_SPHERES: Case = Case {
+    name: "three_spheres",
+    scene: SceneKind::ThreeSpheres,
+};
+
+const CORNELL_BOX: Case = Case {
+    name: "cornell_box",
+    scene: SceneKind::CornellBox,
+};
+
+const GLASS_CAUSTIC: Case = Case {
+    name: "glass_caustic",
+    scene: SceneKind::GlassCaustic,
+};
+
+const DETAIL: Case = Case {
+    name: "detail",
+    scene: SceneKind::Detail,
+};
+
+const LIGHTS: Case = Case {
+    name: "lights",
+    scene: SceneKind::Lights,
+};
+
+#[test]
+fn three_spheres() {
+    THREE_SPHERES.check();
+}
+
+#[test]
+fn cornell_box() {
+    CORNELL_BOX.check();
+}
+
+#[test]
+fn glass_caustic() {
+    GLASS_CAUSTIC.check();
+}
+
+#[test]
+fn detail() {
+    DETAIL.check();
+}
+
+#[test]
+fn lights() {
+    LIGHTS.check();
+}
+
+/// At the same sample count, stratified, Halton and Sobol sampling come
+/// closer to the references than independent random numbers.
+#[test]
+fn low_discrepancy_samplers_lower_the_error() {
+    for case in [THREE_SPHERES, CORNELL_BOX, GLASS_CAUSTIC] {
+        let path = references().join(format!("{}.pfm", case.name));
+        let reference = Image::read(&path).unwrap();
+        let rmse = |sampler| {
+            let image = case.render(16, SEED, sampler);
+            Metrics::compute(&reference, &image).0.rmse
+        };
+        let independent = rmse(SamplerKind::Independent);
+        for sampler in [
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            let error = rmse(sampler);
+            assert!(
+                error < 0.9 * independent,
+                "{}: RMSE {error:.4} vs {independent:.4} with independent samples",
+                case.name
+            );
+        }
+    }
+}
@@ -0,0 +1,450 @@
+use std::cell::RefCell;
+
+use crate::common;
+
+/// Source of the sample values used for one camera sample: pixel jitter
+/// first, then the lens, then whatever the materials ask for along the path.
+/// Each call to `get_1d` or `get_2d` consumes the next dimension(s).
+pub trait Sampler {
+    fn start_sample(&mut self, pixel: u64, sample_index: u64);
+    fn get_1d(&mut self) -> f64;
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Clone, Copy, Default)]
+pub enum SamplerKind {
+    #[default]
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerKind {
+    /// `samples_per_pixel` is the expected sample count; the stratified and
+    /// Sobol samplers distribute their points best over exactly that many.
+    pub fn create(self, samples_per_pixel: u64, seed: u64) -> Box<dyn Sampler> {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(samples_per_pixel, seed)),
+        }
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<Box<dyn Sampler>>> = const { RefCell::new(None) };
+}
+
+/// Makes `sampler` the calling thread's sampler until `uninstall`.
+pub fn install(sampler: Box<dyn Sampler>) {
+    CURRENT.set(Some(sampler));
+}
+
+pub fn uninstall() {
+    CURRENT.set(None);
+}
+
+pub fn start_sample(pixel: u64, sample_index: u64) {
+    CURRENT.with_borrow_mut(|sampler| {
+        if let Some(sampler) = sampler {
+            sampler.start_sample(pixel, sample_index);
+        }
+    });
+}
+
+/// Next sample dimension from the installed sampler, or a plain random
+/// number outside of rendering (e.g. while building a scene).
+pub fn get_1d() -> f64 {
+    CURRENT.with_borrow_mut(|sampler| match sampler {
+        Some(sampler) => sampler.get_1d(),
+        None => common::random_double(),
+    })
+}
+
+pub fn get_2d() -> (f64, f64) {
+    CURRENT.with_borrow_mut(|sampler| match sampler {
+        Some(sampler) => sampler.get_2d(),
+        None => (common::random_double(), common::random_double()),
+    })
+}
+
+/// Uniform random numbers from the per-sample stream.
+pub struct IndependentSampler;
+
+impl Sampler for IndependentSampler {
+    fn start_sample(&mut self, _pixel: u64, _sample_index: u64) {}
+
+    fn get_1d(&mut self) -> f64 {
+        common::random_double()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (common::random_double(), common::random_double())
+    }
+}
+
+/// Jittered stratification: each dimension is split into one stratum per
+/// sample (a square grid for 2D) and the samples visit the strata in an
+/// order shuffled per pixel and dimension.
+pub struct StratifiedSampler {
+    samples_per_pixel: u64,
+    grid: u64,
+    seed: u64,
+    pixel: u64,
+    sample_index: u64,
+    dimension: u64,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u64, seed: u64) -> Self {
+        StratifiedSampler {
+            samples_per_pixel,
+            grid: (samples_per_pixel as f64).sqrt().ceil() as u64,
+            seed,
+            pixel: 0,
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn stratum(&mut self, count: u64) -> u64 {
+        let hash = hash3(self.pixel, self.dimension, self.seed);
+        self.dimension += 1;
+        permutation_element(self.sample_index % count, count, hash)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, pixel: u64, sample_index: u64) {
+        self.pixel = pixel;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let stratum = self.stratum(self.samples_per_pixel);
+        (stratum as f64 + common::random_double()) / self.samples_per_pixel as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let n = self.grid;
+        let stratum = self.stratum(n * n);
+        self.dimension += 1;
+        (
+            ((stratum % n) as f64 + common::random_double()) / n as f64,
+            ((stratum / n) as f64 + common::random_double()) / n as f64,
+        )
+    }
+}
+
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+/// Halton sequence in the first prime bases, decorrelated between pixels by
+/// a random toroidal shift per pixel and dimension. Dimensions beyond the
+/// prime table fall back to independent samples.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: u64,
+    sample_index: u64,
+    dimension: usize,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        HaltonSampler {
+            seed,
+            pixel: 0,
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, pixel: u64, sample_index: u64) {
+        self.pixel = pixel;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        let Some(&base) = PRIMES.get(dimension) else {
+            return common::random_double();
+        };
+
+        let shift = to_unit(hash3(self.pixel, dimension as u64, self.seed) as u32);
+        let x = radical_inverse(base, self.sample_index) + shift;
+        if x >= 1.0 { x - 1.0 } else { x }
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+/// Owen-scrambled Sobol points. Every pair of dimensions uses the first two
+/// Sobol dimensions with its own index shuffle and scramble, which keeps the
+/// excellent 2D stratification of those dimensions without needing tables
+/// for hundreds of them.
+pub struct SobolSampler {
+    // Sample count rounded up to a power of two
+    samples_per_pixel: u64,
+    seed: u64,
+    pixel: u64,
+    sample_index: u64,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(samples_per_pixel: u64, seed: u64) -> Self {
+        SobolSampler {
+            samples_per_pixel: samples_per_pixel.next_power_of_two(),
+            seed,
+            pixel: 0,
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn shuffled_index(&self, hash: u64) -> u64 {
+        // Only the low bits are shuffled so that indices past the expected
+        // count still walk through fresh points.
+        let n = self.samples_per_pixel;
+        let high = self.sample_index & !(n - 1);
+        high | permutation_element(self.sample_index & (n - 1), n, hash)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, pixel: u64, sample_index: u64) {
+        self.pixel = pixel;
+        self.sample_index = sample_index;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let hash = hash3(self.pixel, self.dimension, self.seed);
+        self.dimension += 1;
+        let index = self.shuffled_index(hash);
+        to_unit(owen_scramble(sobol_dim0(index), mix(hash) as u32))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let hash = hash3(self.pixel, self.dimension, self.seed);
+        self.dimension += 2;
+        let index = self.shuffled_index(hash);
+        (
+            to_unit(owen_scramble(sobol_dim0(index), mix(hash ^ 1) as u32)),
+            to_unit(owen_scramble(sobol_dim1(index), mix(hash ^ 2) as u32)),
+        )
+    }
+}
+
+fn radical_inverse(base: u64, mut a: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_m = 1.0;
+    let mut reversed = 0u64;
+    while a > 0 {
+        let next = a / base;
+        reversed = reversed * base + (a - next * base);
+        inv_base_m *= inv_base;
+        a = next;
+    }
+    (reversed as f64 * inv_base_m).min(1.0 - f64::EPSILON)
+}
+
+// First Sobol dimension: the van der Corput sequence in base 2
+fn sobol_dim0(index: u64) -> u32 {
+    (index as u32).reverse_bits()
+}
+
+// Second Sobol dimension, from the direction numbers of the polynomial x + 1
+fn sobol_dim1(index: u64) -> u32 {
+    let mut v = 1u32 << 31;
+    let mut result = 0;
+    let mut i = index as u32;
+    while i != 0 {
+        if i & 1 != 0 {
+            result ^= v;
+        }
+        i >>= 1;
+        v ^= v >> 1;
+    }
+    result
+}
+
+// Nested uniform scrambling: every bit is flipped based on a hash of the
+// bits above it (Owen 1995, in the hashed form used by pbrt-v4).
+fn owen_scramble(mut v: u32, seed: u32) -> u32 {
+    if seed & 1 != 0 {
+        v ^= 1 << 31;
+    }
+    for b in 1..32 {
+        let mask = u32::MAX << (32 - b);
+        if (mix(((v & mask) ^ seed) as u64) as u32) & (1 << b) != 0 {
+            v ^= 1 << (31 - b);
+        }
+    }
+    v
+}
+
+fn to_unit(bits: u32) -> f64 {
+    bits as f64 * (1.0 / 4294967296.0)
+}
+
+// Element `i` of a random permutation of 0..l chosen by `p` (Kensler 2013)
+fn permutation_element(i: u64, l: u64, p: u64) -> u64 {
+    if l <= 1 {
+        return 0;
+    }
+    let p = p as u32;
+    let l = l as u32;
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    let mut x = i as u32;
+    loop {
+        x ^= p;
+        x = x.wrapping_mul(0xe170893d);
+        x ^= p >> 16;
+        x ^= (x & w) >> 4;
+        x ^= p >> 8;
+        x = x.wrapping_mul(0x0929eb3f);
+        x ^= p >> 23;
+        x ^= (x & w) >> 1;
+        x = x.wrapping_mul(1 | p >> 27);
+        x = x.wrapping_mul(0x6935fa69);
+        x ^= (x & w) >> 11;
+        x = x.wrapping_mul(0x74dcb303);
+        x ^= (x & w) >> 2;
+        x = x.wrapping_mul(0x9e501cc3);
+        x ^= (x & w) >> 2;
+        x = x.wrapping_mul(0xc860a3df);
+        x &= w;
+        x ^= x >> 5;
+        if x < l {
+            break;
+        }
+    }
+    ((x as u64).wrapping_add(p as u64)) % l as u64
+}
+
+fn hash3(a: u64, b: u64, c: u64) -> u64 {
+    mix(a ^ mix(b ^ mix(c)))
+}
+
+fn mix(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stratified_samples_fill_every_stratum_once() {
+        let mut sampler = StratifiedSampler::new(16, 7);
+        let mut seen_1d = [false; 16];
+        let mut seen_2d = [[false; 4]; 4];
+        for i in 0..16 {
+            sampler.start_sample(3, i);
+            let (x, y) = sampler.get_2d();
+            let v = sampler.get_1d();
+            seen_2d[(y * 4.0) as usize][(x * 4.0) as usize] = true;
+            seen_1d[(v * 16.0) as usize] = true;
+        }
+        assert!(seen_1d.iter().all(|s| *s));
+        assert!(seen_2d.iter().flatten().all(|s| *s));
+    }
+
+    #[test]
+    fn radical_inverse_mirrors_digits() {
+        assert_eq!(radical_inverse(2, 0), 0.0);
+        assert_eq!(radical_inverse(2, 1), 0.5);
+        assert_eq!(radical_inverse(2, 6), 0.375);
+        assert!((radical_inverse(3, 5) - 7.0 / 9.0).abs() < 1.0e-15);
+        assert!((radical_inverse(5, 7) - 11.0 / 25.0).abs() < 1.0e-15);
+    }
+
+    #[test]
+    fn sobol_points_match_the_reference() {
+        // Joe and Kuo's first two dimensions, in index rather than Gray code
+        // order
+        let dim0 = [0.0, 0.5, 0.25, 0.75, 0.125, 0.625, 0.375, 0.875];
+        let dim1 = [0.0, 0.5, 0.75, 0.25, 0.625, 0.125, 0.375, 0.875];
+        for i in 0..8 {
+            assert_eq!(to_unit(sobol_dim0(i as u64)), dim0[i]);
+            assert_eq!(to_unit(sobol_dim1(i as u64)), dim1[i]);
+        }
+    }
+
+    #[test]
+    fn scrambled_points_stay_stratified_in_the_unit_square() {
+        let mut sobol = SobolSampler::new(16, 7);
+        let mut halton = HaltonSampler::new(7);
+        let mut seen = [[false; 4]; 4];
+        for i in 0..16 {
+            sobol.start_sample(3, i);
+            halton.start_sample(3, i);
+            let (x, y) = sobol.get_2d();
+            seen[(y * 4.0) as usize][(x * 4.0) as usize] = true;
+            for _ in 0..40 {
+                let values = [sobol.get_1d(), halton.get_1d()];
+                assert!(values.iter().all(|v| (0.0..1.0).contains(v)));
+            }
+        }
+        assert!(seen.iter().flatten().all(|s| *s));
+    }
+
+    /// At the same sample count, the low-discrepancy samplers estimate the
+    /// area of a quarter disk more accurately than independent samples.
+    #[test]
+    fn low_discrepancy_samplers_lower_the_error() {
+        let rmse = |kind: SamplerKind| {
+            common::seed_thread(1);
+            let mut sampler = kind.create(16, 7);
+            let mut squared_error = 0.0;
+            for pixel in 0..256 {
+                let mut inside = 0;
+                for i in 0..16 {
+                    sampler.start_sample(pixel, i);
+                    let (x, y) = sampler.get_2d();
+                    if x * x + y * y < 1.0 {
+                        inside += 1;
+                    }
+                }
+                squared_error += (inside as f64 / 16.0 - std::f64::consts::FRAC_PI_4).powi(2);
+            }
+            (squared_error / 256.0).sqrt()
+        };
+        let independent = rmse(SamplerKind::Independent);
+        for kind in [
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            let error = rmse(kind);
+            assert!(
+                error < 0.9 * independent,
+                "RMSE {error:.4} vs {independent:.4}"
+            );
+        }
+    }
+}
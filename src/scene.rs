@@ -0,0 +1,39 @@
+use std::sync::Arc;
+
+use crate::hittable_list::HittableList;
+use crate::light::Light;
+
+/// The scenes the binary can render, each framed by its own view.
+#[derive(Clone, Copy, Default)]
+pub enum SceneKind {
+    // The book's final scene of random spheres
+    #[default]
+    Final,
+    ThreeSpheres,
+    CornellBox,
+    GlassCaustic,
+    // Textures, normal and bump maps, layered materials and cutouts
+    Detail,
+    // Point, spot and directional lights, with IES profiles if available
+    Lights,
+}
+
+/// Everything the camera renders: the geometry plus any analytic lights.
+#[derive(Default)]
+pub struct Scene {
+    pub world: HittableList,
+    pub lights: Vec<Arc<dyn Light>>,
+}
+
+impl Scene {
+    pub fn new(world: HittableList) -> Self {
+        Scene {
+            world,
+            lights: Vec::new(),
+        }
+    }
+
+    pub fn add_light(&mut self, light: Arc<dyn Light>) {
+        self.lights.push(light);
+    }
+}
@@ -1,14 +1,16 @@
 use std::sync::Arc;
 
-use crate::hittable::{HitRecord, Hittable};
+use crate::common;
+use crate::hittable::{AlphaMask, HitRecord, Hittable};
 use crate::material::Material;
 use crate::ray::Ray;
-use crate::vec3::{self, Point3};
+use crate::vec3::{self, Point3, Vec3};
 
 pub struct Sphere {
     center: Point3,
     radius: f64,
     mat: Arc<dyn Material>,
+    alpha_mask: Option<Arc<AlphaMask>>,
 }
 
 impl Sphere {
@@ -17,8 +19,24 @@ impl Sphere {
             center,
             radius,
             mat,
+            alpha_mask: None,
         }
     }
+
+    pub fn with_alpha_mask(mut self, mask: Arc<AlphaMask>) -> Self {
+        self.alpha_mask = Some(mask);
+        self
+    }
+
+    fn get_sphere_uv(p: &Point3) -> (f64, f64) {
+        // p: a given point on the sphere of radius one, centered at the origin.
+        // u: returned value [0,1] of angle around the Y axis from X=-1.
+        // v: returned value [0,1] of angle from Y=-1 to Y=+1.
+        let theta = f64::acos(-p.y());
+        let phi = f64::atan2(-p.z(), p.x()) + common::PI;
+
+        (phi / (2.0 * common::PI), theta / common::PI)
+    }
 }
 
 impl Hittable for Sphere {
@@ -35,14 +53,22 @@ impl Hittable for Sphere {
 
         let sqrt_d = f64::sqrt(discriminant);
 
-        // Nearest root that lies in the acceptable range
-        let mut root = (-h - sqrt_d) / a;
-        if root <= t_min || root >= t_max {
-            root = (-h + sqrt_d) / a;
-            if root <= t_min || root >= t_max {
-                return false;
-            }
-        }
+        // Nearest root that lies in the acceptable range and is not cut out by
+        // the alpha mask
+        let root = [(-h - sqrt_d) / a, (-h + sqrt_d) / a]
+            .into_iter()
+            .find(|&root| {
+                root > t_min
+                    && root < t_max
+                    && self.alpha_mask.as_ref().is_none_or(|mask| {
+                        let p = ray.at(root);
+                        let (u, v) = Self::get_sphere_uv(&((p - self.center) / self.radius));
+                        mask.is_opaque(u, v, &p)
+                    })
+            });
+        let Some(root) = root else {
+            return false;
+        };
 
         // root
         rec.t = root;
@@ -53,7 +79,112 @@ impl Hittable for Sphere {
         // normal (unit length)
         let outwards_normal = (rec.p - self.center) / self.radius;
         rec.set_face_normal(ray, outwards_normal);
+        (rec.u, rec.v) = Self::get_sphere_uv(&outwards_normal);
+        // dp/du points around the Y axis in the direction of increasing phi
+        let dpdu = Vec3::new(outwards_normal.z(), 0.0, -outwards_normal.x());
+        rec.set_tangent_frame(outwards_normal, dpdu);
         rec.mat = Some(self.mat.clone());
         true
     }
+
+    fn materials(&self) -> Vec<&Arc<dyn Material>> {
+        vec![&self.mat]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable_list::HittableList;
+    use crate::image::Image;
+    use crate::material::Lambertian;
+    use crate::quad::Quad;
+    use crate::texture::ImageTexture;
+
+    fn hit(sphere: &Sphere, origin: Point3, direction: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::new();
+        sphere
+            .hit(&Ray::new(origin, direction), 0.001, f64::INFINITY, &mut rec)
+            .then_some(rec)
+    }
+
+    #[test]
+    fn tangent_frame_is_orthonormal_and_follows_dpdu() {
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = Sphere::new(Point3::new(1.0, 2.0, 3.0), 2.0, material);
+        let origin = Point3::new(0.5, 2.5, 10.0);
+        for target in [
+            Point3::new(1.0, 2.0, 3.0),
+            Point3::new(1.7, 2.4, 3.0),
+            Point3::new(-0.1, 1.1, 3.5),
+            Point3::new(1.2, 3.5, 2.0),
+        ] {
+            let direction = target - origin;
+            let rec = hit(&sphere, origin, direction).unwrap();
+            let n = rec.outwards_normal();
+            assert!((rec.tangent.length() - 1.0).abs() < 1.0e-12);
+            assert!((rec.bitangent.length() - 1.0).abs() < 1.0e-12);
+            for (a, b) in [
+                (rec.tangent, n),
+                (rec.bitangent, n),
+                (rec.tangent, rec.bitangent),
+            ] {
+                assert!(vec3::dot(a, b).abs() < 1.0e-12);
+            }
+
+            // A step along the tangent moves the hit towards larger u at
+            // the same v
+            let moved = hit(&sphere, origin + 1.0e-5 * rec.tangent, direction).unwrap();
+            let (du, dv) = (moved.u - rec.u, moved.v - rec.v);
+            assert!(du > 0.0 && dv.abs() < 1.0e-3 * du);
+        }
+    }
+
+    /// Mask whose left and right halves in u have the given opacities.
+    fn mask(left: f64, right: f64) -> Arc<AlphaMask> {
+        let mut image = Image::new(2, 1);
+        image.set_pixel(0, 0, Color::new(left, left, left));
+        image.set_pixel(1, 0, Color::new(right, right, right));
+        Arc::new(AlphaMask::new(Arc::new(ImageTexture::new(image)), 0.5))
+    }
+
+    /// `object` in front of a wall at z = -5, and the ID and hit record of
+    /// what a ray from (x, y, 5) along -z meets first.
+    fn first_hit(object: Box<dyn Hittable>, x: f64, y: f64) -> (usize, HitRecord) {
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut world = HittableList::new();
+        world.add(object);
+        world.add(Box::new(Quad::new(
+            Point3::new(-10.0, -10.0, -5.0),
+            Vec3::new(20.0, 0.0, 0.0),
+            Vec3::new(0.0, 20.0, 0.0),
+            material,
+        )));
+        let ray = Ray::new(Point3::new(x, y, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::new();
+        assert!(world.hit(&ray, 0.001, f64::INFINITY, &mut rec));
+        (rec.object_id, rec)
+    }
+
+    #[test]
+    fn masked_hits_fall_through() {
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let sphere = |mask| {
+            Box::new(Sphere::new(Point3::default(), 1.0, material.clone()).with_alpha_mask(mask))
+        };
+
+        // Fully cut out, the ray goes on to the wall
+        let (id, rec) = first_hit(sphere(mask(0.0, 0.0)), 0.3, 0.0);
+        assert_eq!(id, 1);
+        assert!((rec.t - 10.0).abs() < 1.0e-9);
+
+        // The near side lies at u < 0.5 and is cut out, the far side is not
+        let (id, rec) = first_hit(sphere(mask(0.0, 1.0)), 0.3, 0.0);
+        assert_eq!(id, 0);
+        assert!(rec.p.z() < 0.0 && rec.u > 0.5);
+        let (id, rec) = first_hit(sphere(mask(1.0, 0.0)), 0.3, 0.0);
+        assert_eq!(id, 0);
+        assert!(rec.p.z() > 0.0 && rec.u < 0.5);
+    }
 }
@@ -0,0 +1,147 @@
+use crate::tile::Tile;
+
+/// Where the two eye images go in the rendered image.
+#[derive(Clone, Copy, Default)]
+pub enum StereoLayout {
+    // Left eye on the left, right eye on the right
+    #[default]
+    SideBySide,
+    // Left eye on top, right eye below
+    TopBottom,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+/// Settings for rendering a stereo pair into one image. The eyes sit
+/// `interocular` apart along the camera's right vector and look in the same
+/// direction. A perspective pair converges by shifting each eye's image
+/// rather than turning the eyes in, which would add vertical parallax.
+#[derive(Clone, Copy)]
+pub struct Stereo {
+    pub layout: StereoLayout,
+    // Distance between the eyes in world units
+    pub interocular: f64,
+    // Distance at which the eyes' images coincide; None uses the focus
+    // distance
+    pub convergence: Option<f64>,
+}
+
+impl Default for Stereo {
+    fn default() -> Self {
+        Stereo {
+            layout: StereoLayout::default(),
+            interocular: 0.064,
+            convergence: None,
+        }
+    }
+}
+
+impl Stereo {
+    /// Size of the image holding both eyes' `width` by `height` images.
+    pub fn film_size(&self, width: usize, height: usize) -> (usize, usize) {
+        match self.layout {
+            StereoLayout::SideBySide => (2 * width, height),
+            StereoLayout::TopBottom => (width, 2 * height),
+        }
+    }
+
+    /// Size of each eye's image within an image of the given size; the
+    /// inverse of `film_size`.
+    pub fn eye_size(&self, width: usize, height: usize) -> (usize, usize) {
+        match self.layout {
+            StereoLayout::SideBySide => (width / 2, height),
+            StereoLayout::TopBottom => (width, height / 2),
+        }
+    }
+
+    /// The eye that image pixel (x, y) belongs to and the pixel's position in
+    /// that eye's `width` by `height` image. Rows count from the top.
+    pub fn split(&self, x: usize, y: usize, width: usize, height: usize) -> (Eye, usize, usize) {
+        match self.layout {
+            StereoLayout::SideBySide if x >= width => (Eye::Right, x - width, y),
+            StereoLayout::TopBottom if y >= height => (Eye::Right, x, y - height),
+            _ => (Eye::Left, x, y),
+        }
+    }
+
+    /// The part of the image showing `eye`.
+    pub fn region(&self, eye: Eye, width: usize, height: usize) -> Tile {
+        let (x0, y0) = match (eye, self.layout) {
+            (Eye::Left, _) => (0, 0),
+            (Eye::Right, StereoLayout::SideBySide) => (width, 0),
+            (Eye::Right, StereoLayout::TopBottom) => (0, height),
+        };
+        Tile {
+            x0,
+            y0,
+            x1: x0 + width,
+            y1: y0 + height,
+        }
+    }
+
+    /// Signed distance of the eye from the middle, positive to the right.
+    pub fn offset(&self, eye: Eye) -> f64 {
+        match eye {
+            Eye::Left => -self.interocular / 2.0,
+            Eye::Right => self.interocular / 2.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_side_by_side() {
+        let stereo = Stereo::default();
+        assert_eq!(stereo.film_size(4, 3), (8, 3));
+        assert_eq!(stereo.eye_size(8, 3), (4, 3));
+        assert_eq!(stereo.split(3, 2, 4, 3), (Eye::Left, 3, 2));
+        assert_eq!(stereo.split(4, 2, 4, 3), (Eye::Right, 0, 2));
+
+        let region = stereo.region(Eye::Right, 4, 3);
+        assert_eq!((region.x0, region.y0, region.x1, region.y1), (4, 0, 8, 3));
+        let region = stereo.region(Eye::Left, 4, 3);
+        assert_eq!((region.x0, region.y0, region.x1, region.y1), (0, 0, 4, 3));
+    }
+
+    #[test]
+    fn splits_top_bottom() {
+        let stereo = Stereo {
+            layout: StereoLayout::TopBottom,
+            ..Stereo::default()
+        };
+        assert_eq!(stereo.film_size(4, 3), (4, 6));
+        assert_eq!(stereo.eye_size(4, 6), (4, 3));
+        assert_eq!(stereo.split(3, 2, 4, 3), (Eye::Left, 3, 2));
+        assert_eq!(stereo.split(3, 5, 4, 3), (Eye::Right, 3, 2));
+
+        let region = stereo.region(Eye::Right, 4, 3);
+        assert_eq!((region.x0, region.y0, region.x1, region.y1), (0, 3, 4, 6));
+        let region = stereo.region(Eye::Left, 4, 3);
+        assert_eq!((region.x0, region.y0, region.x1, region.y1), (0, 0, 4, 3));
+    }
+
+    #[test]
+    fn every_pixel_lies_in_its_eyes_region() {
+        for layout in [StereoLayout::SideBySide, StereoLayout::TopBottom] {
+            let stereo = Stereo {
+                layout,
+                ..Stereo::default()
+            };
+            let (width, height) = stereo.film_size(5, 3);
+            for y in 0..height {
+                for x in 0..width {
+                    let (e, ex, ey) = stereo.split(x, y, 5, 3);
+                    let region = stereo.region(e, 5, 3);
+                    assert_eq!((region.x0 + ex, region.y0 + ey), (x, y));
+                }
+            }
+        }
+    }
+}
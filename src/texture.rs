@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use crate::color::Color;
+use crate::colorspace;
+use crate::image::Image;
+use crate::perlin::Perlin;
+use crate::vec3::Point3;
+
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
+
+pub struct SolidColor {
+    albedo: Color,
+}
+
+impl SolidColor {
+    /// `albedo` is linear Rec.709.
+    pub fn new(albedo: Color) -> Self {
+        SolidColor {
+            albedo: colorspace::input(albedo),
+        }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.albedo
+    }
+}
+
+pub struct CheckerTexture {
+    inv_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        CheckerTexture {
+            inv_scale: 1.0 / scale,
+            even,
+            odd,
+        }
+    }
+
+    pub fn from_colors(scale: f64, c1: Color, c2: Color) -> Self {
+        Self::new(
+            scale,
+            Arc::new(SolidColor::new(c1)),
+            Arc::new(SolidColor::new(c2)),
+        )
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let x = (self.inv_scale * p.x()).floor() as i64;
+        let y = (self.inv_scale * p.y()).floor() as i64;
+        let z = (self.inv_scale * p.z()).floor() as i64;
+
+        if (x + y + z) % 2 == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+pub struct ImageTexture {
+    image: Image,
+}
+
+impl ImageTexture {
+    pub fn new(image: Image) -> Self {
+        ImageTexture { image }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        if self.image.height() == 0 {
+            // Debugging aid: solid cyan when there is no texture data
+            return Color::new(0.0, 1.0, 1.0);
+        }
+
+        // Wrap texture coordinates, flipping v to image coordinates
+        let u = u - u.floor();
+        let v = 1.0 - (v - v.floor());
+
+        let i = ((u * self.image.width() as f64) as usize).min(self.image.width() - 1);
+        let j = ((v * self.image.height() as f64) as usize).min(self.image.height() - 1);
+        self.image.pixel(i, j)
+    }
+}
+
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: f64,
+}
+
+impl NoiseTexture {
+    pub fn new(scale: f64) -> Self {
+        NoiseTexture {
+            noise: Perlin::new(),
+            scale,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let marble = 1.0 + f64::sin(self.scale * p.z() + 10.0 * self.noise.turb(p, 7));
+        Color::new(0.5, 0.5, 0.5) * marble
+    }
+}
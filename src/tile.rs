@@ -0,0 +1,128 @@
+/// A rectangle of pixels rendered as one unit of work.
+#[derive(Clone, Copy)]
+pub struct Tile {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+#[derive(Clone, Copy, Default)]
+pub enum TileOrder {
+    // Row by row from the top-left
+    Scanline,
+    // Outwards from the image center, so the subject resolves first
+    #[default]
+    Spiral,
+    // Along a Hilbert curve, which keeps consecutive tiles close together
+    Hilbert,
+}
+
+/// Splits a `width` x `height` image into tiles of at most `size` pixels a
+/// side, listed in the order they should be rendered. `size` must be
+/// positive.
+pub fn tiles(width: usize, height: usize, size: usize, order: TileOrder) -> Vec<Tile> {
+    debug_assert!(size > 0);
+    let nx = width.div_ceil(size);
+    let ny = height.div_ceil(size);
+
+    let coords = match order {
+        TileOrder::Scanline => (0..ny)
+            .flat_map(|ty| (0..nx).map(move |tx| (tx, ty)))
+            .collect(),
+        TileOrder::Spiral => spiral(nx, ny),
+        TileOrder::Hilbert => hilbert(nx, ny),
+    };
+
+    coords
+        .into_iter()
+        .map(|(tx, ty)| Tile {
+            x0: tx * size,
+            y0: ty * size,
+            x1: ((tx + 1) * size).min(width),
+            y1: ((ty + 1) * size).min(height),
+        })
+        .collect()
+}
+
+fn spiral(nx: usize, ny: usize) -> Vec<(usize, usize)> {
+    let total = nx * ny;
+    let mut coords = Vec::with_capacity(total);
+    let (mut x, mut y) = ((nx as i64 - 1) / 2, (ny as i64 - 1) / 2);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut leg = 1;
+    let mut dir = 0;
+
+    // Walk legs of length 1, 1, 2, 2, 3, 3, ... keeping in-bounds cells
+    while coords.len() < total {
+        for _ in 0..2 {
+            for _ in 0..leg {
+                if (0..nx as i64).contains(&x) && (0..ny as i64).contains(&y) {
+                    coords.push((x as usize, y as usize));
+                }
+                x += directions[dir].0;
+                y += directions[dir].1;
+            }
+            dir = (dir + 1) % 4;
+        }
+        leg += 1;
+    }
+
+    coords
+}
+
+fn hilbert(nx: usize, ny: usize) -> Vec<(usize, usize)> {
+    let n = nx.max(ny).next_power_of_two();
+    (0..n * n)
+        .map(|d| hilbert_d2xy(n, d))
+        .filter(|&(x, y)| x < nx && y < ny)
+        .collect()
+}
+
+// Maps a distance along the Hilbert curve filling an n x n grid to a cell.
+fn hilbert_d2xy(n: usize, d: usize) -> (usize, usize) {
+    let (mut x, mut y) = (0, 0);
+    let mut t = d;
+    let mut s = 1;
+
+    while s < n {
+        let rx = 1 & (t / 2);
+        let ry = 1 & (t ^ rx);
+        if ry == 0 {
+            if rx == 1 {
+                x = s - 1 - x;
+                y = s - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        x += s * rx;
+        y += s * ry;
+        t /= 4;
+        s *= 2;
+    }
+
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_cover_every_pixel_once() {
+        for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            for (width, height, size) in [(37, 23, 8), (100, 7, 16), (5, 5, 32), (64, 64, 16)] {
+                let mut covered = vec![0; width * height];
+                for tile in tiles(width, height, size, order) {
+                    assert!(tile.x1 - tile.x0 <= size && tile.y1 - tile.y0 <= size);
+                    for y in tile.y0..tile.y1 {
+                        for x in tile.x0..tile.x1 {
+                            covered[y * width + x] += 1;
+                        }
+                    }
+                }
+                assert!(covered.iter().all(|&n| n == 1));
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use std::sync::Arc;
+
+use crate::hittable::{AlphaMask, HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::vec3::{self, Point3, Vec3};
+
+pub struct Triangle {
+    vertices: [Point3; 3],
+    uvs: [(f64, f64); 3],
+    normal: Vec3,
+    dpdu: Vec3,
+    mat: Arc<dyn Material>,
+    alpha_mask: Option<Arc<AlphaMask>>,
+}
+
+impl Triangle {
+    pub fn new(a: Point3, b: Point3, c: Point3, mat: Arc<dyn Material>) -> Self {
+        Self::with_uvs(a, b, c, [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)], mat)
+    }
+
+    pub fn with_uvs(
+        a: Point3,
+        b: Point3,
+        c: Point3,
+        uvs: [(f64, f64); 3],
+        mat: Arc<dyn Material>,
+    ) -> Self {
+        let e1 = b - a;
+        let e2 = c - a;
+        let normal = vec3::unit_vector(vec3::cross(e1, e2));
+
+        // Solve e1 = du1 * dpdu + dv1 * dpdv, e2 = du2 * dpdu + dv2 * dpdv
+        let (du1, dv1) = (uvs[1].0 - uvs[0].0, uvs[1].1 - uvs[0].1);
+        let (du2, dv2) = (uvs[2].0 - uvs[0].0, uvs[2].1 - uvs[0].1);
+        let det = du1 * dv2 - du2 * dv1;
+        let dpdu = if det.abs() > 1.0e-12 {
+            (dv2 * e1 - dv1 * e2) / det
+        } else {
+            e1
+        };
+
+        Triangle {
+            vertices: [a, b, c],
+            uvs,
+            normal,
+            dpdu,
+            mat,
+            alpha_mask: None,
+        }
+    }
+
+    pub fn with_alpha_mask(mut self, mask: Arc<AlphaMask>) -> Self {
+        self.alpha_mask = Some(mask);
+        self
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64, rec: &mut HitRecord) -> bool {
+        // Möller–Trumbore intersection
+        let [a, b, c] = self.vertices;
+        let e1 = b - a;
+        let e2 = c - a;
+        let pvec = vec3::cross(ray.direction(), e2);
+        let det = vec3::dot(e1, pvec);
+        if det.abs() < 1.0e-12 {
+            return false;
+        }
+        let inv_det = 1.0 / det;
+
+        let tvec = ray.origin() - a;
+        let b1 = vec3::dot(tvec, pvec) * inv_det;
+        if !(0.0..=1.0).contains(&b1) {
+            return false;
+        }
+
+        let qvec = vec3::cross(tvec, e1);
+        let b2 = vec3::dot(ray.direction(), qvec) * inv_det;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return false;
+        }
+
+        let t = vec3::dot(e2, qvec) * inv_det;
+        if t <= t_min || t >= t_max {
+            return false;
+        }
+
+        let b0 = 1.0 - b1 - b2;
+        let u = b0 * self.uvs[0].0 + b1 * self.uvs[1].0 + b2 * self.uvs[2].0;
+        let v = b0 * self.uvs[0].1 + b1 * self.uvs[1].1 + b2 * self.uvs[2].1;
+        let p = ray.at(t);
+        if let Some(mask) = &self.alpha_mask
+            && !mask.is_opaque(u, v, &p)
+        {
+            return false;
+        }
+
+        rec.t = t;
+        rec.p = p;
+        rec.u = u;
+        rec.v = v;
+        rec.set_face_normal(ray, self.normal);
+        rec.set_tangent_frame(self.normal, self.dpdu);
+        rec.mat = Some(self.mat.clone());
+        true
+    }
+
+    fn materials(&self) -> Vec<&Arc<dyn Material>> {
+        vec![&self.mat]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::hittable_list::HittableList;
+    use crate::image::Image;
+    use crate::material::Lambertian;
+    use crate::quad::Quad;
+    use crate::texture::ImageTexture;
+
+    fn hit(triangle: &Triangle, origin: Point3, direction: Vec3) -> Option<HitRecord> {
+        let mut rec = HitRecord::new();
+        triangle
+            .hit(&Ray::new(origin, direction), 0.001, f64::INFINITY, &mut rec)
+            .then_some(rec)
+    }
+
+    #[test]
+    fn tangent_frame_is_orthonormal_and_follows_dpdu() {
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        // Texture coordinates sheared and rotated against the edges
+        let triangle = Triangle::with_uvs(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(2.0, 0.5, 0.0),
+            Point3::new(0.5, 2.0, 0.5),
+            [(0.2, 0.1), (0.4, 0.9), (0.9, 0.3)],
+            material,
+        );
+        let direction = Vec3::new(0.02, 0.01, -1.0);
+        let origin = Point3::new(0.8, 0.7, 5.0);
+        let rec = hit(&triangle, origin, direction).unwrap();
+        let n = rec.outwards_normal();
+        assert!((rec.tangent.length() - 1.0).abs() < 1.0e-12);
+        assert!((rec.bitangent.length() - 1.0).abs() < 1.0e-12);
+        for (a, b) in [
+            (rec.tangent, n),
+            (rec.bitangent, n),
+            (rec.tangent, rec.bitangent),
+        ] {
+            assert!(vec3::dot(a, b).abs() < 1.0e-12);
+        }
+
+        // Texture coordinates are affine over the triangle, so a step along
+        // the tangent changes u alone
+        let moved = hit(&triangle, origin + 0.01 * rec.tangent, direction).unwrap();
+        let (du, dv) = (moved.u - rec.u, moved.v - rec.v);
+        assert!(du > 0.0 && dv.abs() < 1.0e-9);
+    }
+
+    /// Mask whose left and right halves in u have the given opacities.
+    fn mask(left: f64, right: f64) -> Arc<AlphaMask> {
+        let mut image = Image::new(2, 1);
+        image.set_pixel(0, 0, Color::new(left, left, left));
+        image.set_pixel(1, 0, Color::new(right, right, right));
+        Arc::new(AlphaMask::new(Arc::new(ImageTexture::new(image)), 0.5))
+    }
+
+    /// `object` in front of a wall at z = -5, and the ID and hit record of
+    /// what a ray from (x, y, 5) along -z meets first.
+    fn first_hit(object: Box<dyn Hittable>, x: f64, y: f64) -> (usize, HitRecord) {
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut world = HittableList::new();
+        world.add(object);
+        world.add(Box::new(Quad::new(
+            Point3::new(-10.0, -10.0, -5.0),
+            Vec3::new(20.0, 0.0, 0.0),
+            Vec3::new(0.0, 20.0, 0.0),
+            material,
+        )));
+        let ray = Ray::new(Point3::new(x, y, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::new();
+        assert!(world.hit(&ray, 0.001, f64::INFINITY, &mut rec));
+        (rec.object_id, rec)
+    }
+
+    #[test]
+    fn masked_hits_fall_through() {
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let triangle = |mask| {
+            Box::new(
+                Triangle::new(
+                    Point3::new(-1.0, -1.0, 0.0),
+                    Point3::new(1.0, -1.0, 0.0),
+                    Point3::new(-1.0, 1.0, 0.0),
+                    material.clone(),
+                )
+                .with_alpha_mask(mask),
+            )
+        };
+
+        assert_eq!(first_hit(triangle(mask(0.0, 0.0)), 0.3, -0.7).0, 1);
+        // u runs from 0 to 1 along the bottom edge
+        assert_eq!(first_hit(triangle(mask(0.0, 1.0)), -0.5, -0.5).0, 1);
+        let (id, rec) = first_hit(triangle(mask(0.0, 1.0)), 0.3, -0.7);
+        assert_eq!(id, 0);
+        assert!((rec.t - 5.0).abs() < 1.0e-9);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::common;
+use crate::sampler;
 use std::fmt::{Display, Formatter, Result};
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub};
 
@@ -53,7 +54,7 @@ impl Vec3 {
 
     pub fn near_zero(&self) -> bool {
         const EPS: f64 = 1.0e-8;
-        self.e.iter().all(|x| *x < EPS)
+        self.e.iter().all(|x| x.abs() < EPS)
     }
 }
 
@@ -151,26 +152,29 @@ pub fn unit_vector(v: Vec3) -> Vec3 {
 }
 
 pub fn random_unit_vector() -> Vec3 {
-    loop {
-        let p = Vec3::random_range(-1.0, 1.0);
-        let lensq = p.length_squared();
-        if 1.0e-160 < lensq && lensq <= 1.0 {
-            return p / f64::sqrt(lensq);
-        }
-    }
+    // Uniform on the sphere from the next two sampler dimensions
+    let (u1, u2) = sampler::get_2d();
+    let z = 1.0 - 2.0 * u1;
+    let r = f64::sqrt(f64::max(0.0, 1.0 - z * z));
+    let phi = 2.0 * common::PI * u2;
+    Vec3::new(r * f64::cos(phi), r * f64::sin(phi), z)
 }
 
 pub fn random_in_unit_disk() -> Vec3 {
-    loop {
-        let p = Vec3::new(
-            common::random_double_range(-1.0, 1.0),
-            common::random_double_range(-1.0, 1.0),
-            0.0,
-        );
-        if p.length_squared() < 1.0 {
-            return p;
-        }
+    // Shirley-Chiu concentric mapping, which keeps the sampler's
+    // stratification intact
+    let (u1, u2) = sampler::get_2d();
+    let (a, b) = (2.0 * u1 - 1.0, 2.0 * u2 - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return Vec3::default();
     }
+
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, common::PI / 4.0 * (b / a))
+    } else {
+        (b, common::PI / 2.0 - common::PI / 4.0 * (a / b))
+    };
+    Vec3::new(r * f64::cos(theta), r * f64::sin(theta), 0.0)
 }
 
 #[allow(unused)]